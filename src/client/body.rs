@@ -0,0 +1,148 @@
+use crate::client::error::{RequestError, RequestErrorKind};
+use crate::models::dsl_model::{BinarySource, Body, Payload};
+use crate::template::{render, render_json, TemplateContext};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hyper::body::Bytes;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::form_urlencoded;
+
+/// Encoded request body together with the content type it should be sent with.
+pub struct EncodedBody {
+    pub bytes: Bytes,
+    pub content_type: String,
+}
+
+/// Reads files and decodes base64 payloads once, so requests don't hit the disk.
+pub fn load_payloads(body: &mut Body) -> Result<(), RequestError> {
+    match body {
+        Body::Binary { source, .. } => load_source(source),
+        Body::Multipart(parts) => {
+            for part in parts.iter_mut() {
+                if let Some(file) = &mut part.file {
+                    load_source(file)?;
+                }
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Encodes the body, filling placeholders in every text field.
+pub fn encode_body(body: &Body, ctx: &TemplateContext) -> Result<EncodedBody, RequestError> {
+    let encoded = match body {
+        Body::Json(json) => {
+            let json_string = serde_json::to_string(&render_json(json, ctx))
+                .map_err(|e| RequestError::new(RequestErrorKind::InvalidBody, e.to_string()))?;
+            EncodedBody {
+                bytes: Bytes::from(json_string),
+                content_type: "application/json".to_string(),
+            }
+        }
+        Body::Xml(xml) => EncodedBody {
+            bytes: Bytes::from(render(xml, ctx).into_owned()),
+            content_type: "application/xml".to_string(),
+        },
+        Body::Form(fields) => {
+            let mut serializer = form_urlencoded::Serializer::new(String::new());
+            for (key, value) in fields {
+                serializer.append_pair(&render(key, ctx), &render(value, ctx));
+            }
+            EncodedBody {
+                bytes: Bytes::from(serializer.finish()),
+                content_type: "application/x-www-form-urlencoded".to_string(),
+            }
+        }
+        Body::Multipart(parts) => {
+            let boundary = multipart_boundary();
+            let mut out = Vec::new();
+
+            for part in parts {
+                out.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+                let mut disposition = format!("Content-Disposition: form-data; name=\"{}\"", escape_quotes(&part.name));
+                if let Some(filename) = &part.filename {
+                    disposition.push_str(&format!("; filename=\"{}\"", escape_quotes(filename)));
+                }
+                out.extend_from_slice(disposition.as_bytes());
+                out.extend_from_slice(b"\r\n");
+
+                let content = match &part.file {
+                    Some(file) => {
+                        let content_type = part.content_type.as_deref().unwrap_or("application/octet-stream");
+                        out.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+                        source_bytes(file)?
+                    }
+                    None => {
+                        if let Some(content_type) = &part.content_type {
+                            out.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+                        }
+                        Bytes::from(render(part.value.as_deref().unwrap_or_default(), ctx).into_owned())
+                    }
+                };
+
+                out.extend_from_slice(b"\r\n");
+                out.extend_from_slice(&content);
+                out.extend_from_slice(b"\r\n");
+            }
+            out.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+            EncodedBody {
+                bytes: Bytes::from(out),
+                content_type: format!("multipart/form-data; boundary={}", boundary),
+            }
+        }
+        Body::Raw { content_type, data } => EncodedBody {
+            bytes: Bytes::from(render(data, ctx).into_owned()),
+            content_type: content_type.clone(),
+        },
+        Body::Binary { content_type, source } => EncodedBody {
+            bytes: source_bytes(source)?,
+            content_type: content_type.clone().unwrap_or_else(|| "application/octet-stream".to_string()),
+        },
+    };
+
+    Ok(encoded)
+}
+
+fn load_source(source: &mut BinarySource) -> Result<(), RequestError> {
+    if source.loaded.is_none() {
+        source.loaded = Some(Payload(read_source(source)?));
+    }
+    Ok(())
+}
+
+fn source_bytes(source: &BinarySource) -> Result<Bytes, RequestError> {
+    match &source.loaded {
+        Some(payload) => Ok(payload.0.clone()),
+        None => read_source(source),
+    }
+}
+
+fn read_source(source: &BinarySource) -> Result<Bytes, RequestError> {
+    match (&source.path, &source.base64) {
+        (Some(path), None) => std::fs::read(path)
+            .map(Bytes::from)
+            .map_err(|e| RequestError::new(RequestErrorKind::InvalidBody, format!("Cannot read {}: {}", path, e))),
+        (None, Some(data)) => BASE64
+            .decode(data)
+            .map(Bytes::from)
+            .map_err(|e| RequestError::new(RequestErrorKind::InvalidBody, format!("Invalid base64 content: {}", e))),
+        _ => Err(RequestError::new(
+            RequestErrorKind::InvalidBody,
+            "Binary content needs exactly one of `path` or `base64`",
+        )),
+    }
+}
+
+fn multipart_boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("----LoadForgeBoundary{:x}", nanos)
+}
+
+fn escape_quotes(value: &str) -> String {
+    value.replace('"', "\\\"")
+}
@@ -0,0 +1,120 @@
+use crate::models::dsl_model::Encoding;
+use flate2::write::{GzDecoder, GzEncoder, ZlibDecoder, ZlibEncoder};
+use flate2::Compression;
+use std::io::{self, Write};
+
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+
+impl Encoding {
+    pub fn as_header(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Br => "br",
+        }
+    }
+
+    fn from_header(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            "br" => Some(Encoding::Br),
+            _ => None,
+        }
+    }
+}
+
+pub fn compress(encoding: Encoding, data: &[u8]) -> io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        Encoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        Encoding::Br => {
+            let mut encoder = brotli::CompressorWriter::new(Vec::new(), BROTLI_BUFFER_SIZE, BROTLI_QUALITY, BROTLI_WINDOW);
+            encoder.write_all(data)?;
+            Ok(encoder.into_inner())
+        }
+    }
+}
+
+/// Counts decoded bytes, keeping them only when asked to.
+#[derive(Default)]
+pub struct CountingSink {
+    written: u64,
+    kept: Option<Vec<u8>>,
+}
+
+impl Write for CountingSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written += buf.len() as u64;
+        if let Some(kept) = self.kept.as_mut() {
+            kept.extend_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Streaming decoder for a compressed response body.
+pub enum Decoder {
+    Gzip(GzDecoder<CountingSink>),
+    Deflate(ZlibDecoder<CountingSink>),
+    Br(Box<brotli::DecompressorWriter<CountingSink>>),
+}
+
+impl Decoder {
+    /// Builds a decoder for the response's `Content-Encoding`, if it is one we
+    /// asked for. With `keep`, the decoded body is returned by `finish`.
+    pub fn for_response(content_encoding: Option<&str>, accepted: &[Encoding], keep: bool) -> Option<Self> {
+        let encoding = Encoding::from_header(content_encoding?)?;
+        if !accepted.contains(&encoding) {
+            return None;
+        }
+
+        let sink = CountingSink {
+            written: 0,
+            kept: keep.then(Vec::new),
+        };
+        Some(match encoding {
+            Encoding::Gzip => Decoder::Gzip(GzDecoder::new(sink)),
+            Encoding::Deflate => Decoder::Deflate(ZlibDecoder::new(sink)),
+            Encoding::Br => Decoder::Br(Box::new(brotli::DecompressorWriter::new(sink, BROTLI_BUFFER_SIZE))),
+        })
+    }
+
+    pub fn write(&mut self, chunk: &[u8]) -> io::Result<()> {
+        match self {
+            Decoder::Gzip(decoder) => decoder.write_all(chunk),
+            Decoder::Deflate(decoder) => decoder.write_all(chunk),
+            Decoder::Br(decoder) => decoder.write_all(chunk),
+        }
+    }
+
+    /// Flushes the decoder and returns the total decompressed size, plus the
+    /// decoded body when it was kept.
+    pub fn finish(self) -> io::Result<(u64, Option<Vec<u8>>)> {
+        let sink = match self {
+            Decoder::Gzip(decoder) => decoder.finish()?,
+            Decoder::Deflate(decoder) => decoder.finish()?,
+            Decoder::Br(mut decoder) => {
+                decoder.close()?;
+                decoder
+                    .into_inner()
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "incomplete brotli stream"))?
+            }
+        };
+        Ok((sink.written, sink.kept))
+    }
+}
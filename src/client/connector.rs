@@ -0,0 +1,185 @@
+use crate::models::dsl_model::TlsConfig;
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use hyper::Uri;
+use hyper_tls::MaybeHttpsStream;
+use native_tls::{Certificate, Identity};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::net::TcpStream;
+
+/// Wraps a connector and counts every new connection it is asked to open.
+#[derive(Clone)]
+pub struct CountingConnector<C> {
+    inner: C,
+    opened: Arc<AtomicU64>,
+}
+
+impl<C> CountingConnector<C> {
+    pub fn new(inner: C, opened: Arc<AtomicU64>) -> Self {
+        Self { inner, opened }
+    }
+}
+
+impl<C> Service<Uri> for CountingConnector<C>
+where
+    C: Service<Uri>,
+    C::Future: Send + 'static,
+{
+    type Response = C::Response;
+    type Error = C::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let opened = Arc::clone(&self.opened);
+        let connecting = self.inner.call(dst);
+
+        Box::pin(async move {
+            let conn = connecting.await?;
+            opened.fetch_add(1, Ordering::Relaxed);
+            Ok(conn)
+        })
+    }
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Raised when the TCP connection succeeds but the TLS handshake does not.
+#[derive(Debug)]
+pub struct TlsHandshakeError(pub native_tls::Error);
+
+impl fmt::Display for TlsHandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TLS handshake failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for TlsHandshakeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// HTTP/HTTPS connector that honours the test's TLS settings, including an
+/// optional server name used for SNI and certificate verification.
+#[derive(Clone)]
+pub struct TlsConnector {
+    http: HttpConnector,
+    tls: tokio_native_tls::TlsConnector,
+    server_name: Option<String>,
+}
+
+impl TlsConnector {
+    pub fn from_config(tls_config: Option<&TlsConfig>) -> Result<Self, String> {
+        let mut builder = native_tls::TlsConnector::builder();
+        let mut server_name = None;
+
+        if let Some(tls_config) = tls_config {
+            if let Some(ca_pem) = &tls_config.ca_cert_pem {
+                let blocks = split_pem_certificates(ca_pem);
+                if blocks.is_empty() {
+                    return Err("CA bundle does not contain any PEM certificate".to_string());
+                }
+                for block in blocks {
+                    let cert = Certificate::from_pem(block.as_bytes())
+                        .map_err(|e| format!("Invalid CA certificate: {}", e))?;
+                    builder.add_root_certificate(cert);
+                }
+            }
+
+            match (&tls_config.client_cert_pem, &tls_config.client_key_pem) {
+                (Some(cert), Some(key)) => {
+                    let identity = Identity::from_pkcs8(cert.as_bytes(), key.as_bytes())
+                        .map_err(|e| format!("Invalid client certificate or key: {}", e))?;
+                    builder.identity(identity);
+                }
+                (None, None) => {}
+                _ => {
+                    return Err("Client certificate and key must be provided together".to_string());
+                }
+            }
+
+            if tls_config.insecure_skip_verify {
+                builder.danger_accept_invalid_certs(true);
+                builder.danger_accept_invalid_hostnames(true);
+            }
+
+            server_name = tls_config.server_name.clone();
+        }
+
+        let tls = builder
+            .build()
+            .map_err(|e| format!("Failed to build TLS connector: {}", e))?;
+
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+
+        Ok(Self {
+            http,
+            tls: tls.into(),
+            server_name,
+        })
+    }
+}
+
+impl Service<Uri> for TlsConnector {
+    type Response = MaybeHttpsStream<TcpStream>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let is_https = dst.scheme_str() == Some("https");
+        let domain = match &self.server_name {
+            Some(name) => name.clone(),
+            None => dst
+                .host()
+                .unwrap_or("")
+                .trim_matches(|c| c == '[' || c == ']')
+                .to_string(),
+        };
+        let connecting = self.http.call(dst);
+        let tls = self.tls.clone();
+
+        Box::pin(async move {
+            let tcp = connecting.await?;
+            if !is_https {
+                return Ok(MaybeHttpsStream::Http(tcp));
+            }
+
+            let stream = tls
+                .connect(&domain, tcp)
+                .await
+                .map_err(TlsHandshakeError)?;
+            Ok(MaybeHttpsStream::Https(stream))
+        })
+    }
+}
+
+fn split_pem_certificates(bundle: &str) -> Vec<String> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+
+    let mut certs = Vec::new();
+    let mut rest = bundle;
+    while let Some(start) = rest.find(BEGIN) {
+        let Some(len) = rest[start..].find(END) else {
+            break;
+        };
+        let end = start + len + END.len();
+        certs.push(rest[start..end].to_string());
+        rest = &rest[end..];
+    }
+    certs
+}
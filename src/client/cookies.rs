@@ -0,0 +1,38 @@
+use cookie_store::CookieStore;
+use hyper::header::{HeaderMap, HeaderValue, COOKIE, SET_COOKIE};
+use hyper::{Body as HyperBody, Request};
+use url::Url;
+
+/// Adds the cookies the jar holds for the request URL, after any `Cookie`
+/// header set in the configuration.
+pub fn add_cookie_header(jar: &CookieStore, url: &Url, request: &mut Request<HyperBody>) {
+    let stored: Vec<String> = jar
+        .get_request_values(url)
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    if stored.is_empty() {
+        return;
+    }
+
+    let mut cookies = request
+        .headers()
+        .get(COOKIE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| vec![v.to_string()])
+        .unwrap_or_default();
+    cookies.extend(stored);
+
+    if let Ok(value) = HeaderValue::from_str(&cookies.join("; ")) {
+        request.headers_mut().insert(COOKIE, value);
+    }
+}
+
+/// Stores every valid `Set-Cookie` of a response. Cookies rejected by the
+/// domain, path or secure rules are ignored, as a browser would.
+pub fn store_cookies(jar: &mut CookieStore, url: &Url, headers: &HeaderMap) {
+    for value in headers.get_all(SET_COOKIE) {
+        if let Ok(value) = value.to_str() {
+            let _ = jar.parse(value, url);
+        }
+    }
+}
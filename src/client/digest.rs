@@ -0,0 +1,320 @@
+use crate::client::error::{RequestError, RequestErrorKind};
+use hyper::header::{HeaderMap, HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use hyper::{Body as HyperBody, Request};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DigestAlgorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+    Sha256Sess,
+}
+
+impl DigestAlgorithm {
+    fn from_param(value: &str) -> Option<Self> {
+        match value.to_ascii_uppercase().as_str() {
+            "MD5" => Some(DigestAlgorithm::Md5),
+            "MD5-SESS" => Some(DigestAlgorithm::Md5Sess),
+            "SHA-256" => Some(DigestAlgorithm::Sha256),
+            "SHA-256-SESS" => Some(DigestAlgorithm::Sha256Sess),
+            _ => None,
+        }
+    }
+
+    fn as_param(&self) -> &'static str {
+        match self {
+            DigestAlgorithm::Md5 => "MD5",
+            DigestAlgorithm::Md5Sess => "MD5-sess",
+            DigestAlgorithm::Sha256 => "SHA-256",
+            DigestAlgorithm::Sha256Sess => "SHA-256-sess",
+        }
+    }
+
+    fn hash(&self, data: &str) -> String {
+        match self {
+            DigestAlgorithm::Md5 | DigestAlgorithm::Md5Sess => hex::encode(Md5::digest(data.as_bytes())),
+            DigestAlgorithm::Sha256 | DigestAlgorithm::Sha256Sess => hex::encode(Sha256::digest(data.as_bytes())),
+        }
+    }
+
+    fn is_session(&self) -> bool {
+        matches!(self, DigestAlgorithm::Md5Sess | DigestAlgorithm::Sha256Sess)
+    }
+}
+
+/// A `WWW-Authenticate: Digest` challenge.
+#[derive(Debug, Clone)]
+pub struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: DigestAlgorithm,
+    /// `true` when the server offered `qop=auth`; `false` for RFC 2069 servers
+    /// that send no `qop` at all.
+    qop_auth: bool,
+    pub stale: bool,
+}
+
+impl DigestChallenge {
+    /// Finds and parses the Digest challenge of a 401 response. Challenges
+    /// using an unknown algorithm or only `qop=auth-int` are ignored.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        headers
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .find_map(Self::parse)
+    }
+
+    fn parse(header: &str) -> Option<Self> {
+        let header = header.trim_start();
+        let (scheme, params) = header.split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("Digest") {
+            return None;
+        }
+
+        let mut realm = None;
+        let mut nonce = None;
+        let mut opaque = None;
+        let mut algorithm = DigestAlgorithm::Md5;
+        let mut qop = None;
+        let mut stale = false;
+
+        for (key, value) in parse_params(params) {
+            match key.to_ascii_lowercase().as_str() {
+                "realm" => realm = Some(value),
+                "nonce" => nonce = Some(value),
+                "opaque" => opaque = Some(value),
+                "algorithm" => algorithm = DigestAlgorithm::from_param(&value)?,
+                "qop" => qop = Some(value),
+                "stale" => stale = value.eq_ignore_ascii_case("true"),
+                _ => {}
+            }
+        }
+
+        let qop_auth = match qop {
+            Some(qop) => {
+                if !qop.split(',').any(|q| q.trim().eq_ignore_ascii_case("auth")) {
+                    return None;
+                }
+                true
+            }
+            None => false,
+        };
+
+        Some(Self {
+            realm: realm.unwrap_or_default(),
+            nonce: nonce?,
+            opaque,
+            algorithm,
+            qop_auth,
+            stale,
+        })
+    }
+}
+
+/// Digest state of one virtual user: the last challenge and how many times
+/// its nonce has been used.
+#[derive(Debug)]
+pub struct DigestSession {
+    challenge: DigestChallenge,
+    nonce_count: u32,
+}
+
+impl DigestSession {
+    pub fn new(challenge: DigestChallenge) -> Self {
+        Self {
+            challenge,
+            nonce_count: 0,
+        }
+    }
+
+    /// Adds an `Authorization: Digest` header answering the current challenge
+    /// with the next nonce count.
+    pub fn authorize(
+        &mut self,
+        request: &mut Request<HyperBody>,
+        username: &str,
+        password: &str,
+    ) -> Result<(), RequestError> {
+        self.nonce_count += 1;
+        let cnonce = format!("{:016x}", rand::random::<u64>());
+        let header = self.authorization(request, username, password, &cnonce);
+
+        let value = HeaderValue::from_str(&header)
+            .map_err(|e| RequestError::new(RequestErrorKind::Auth, format!("Invalid Digest credentials: {}", e)))?;
+        request.headers_mut().insert(AUTHORIZATION, value);
+        Ok(())
+    }
+
+    fn authorization(&self, request: &Request<HyperBody>, username: &str, password: &str, cnonce: &str) -> String {
+        let challenge = &self.challenge;
+        let algorithm = challenge.algorithm;
+        let uri = request
+            .uri()
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or("/");
+        let nc = format!("{:08x}", self.nonce_count);
+
+        let mut ha1 = algorithm.hash(&format!("{}:{}:{}", username, challenge.realm, password));
+        if algorithm.is_session() {
+            ha1 = algorithm.hash(&format!("{}:{}:{}", ha1, challenge.nonce, cnonce));
+        }
+        let ha2 = algorithm.hash(&format!("{}:{}", request.method().as_str(), uri));
+        let response = if challenge.qop_auth {
+            algorithm.hash(&format!("{}:{}:{}:{}:auth:{}", ha1, challenge.nonce, nc, cnonce, ha2))
+        } else {
+            algorithm.hash(&format!("{}:{}:{}", ha1, challenge.nonce, ha2))
+        };
+
+        let mut header = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm={}, response=\"{}\"",
+            quote(username),
+            quote(&challenge.realm),
+            quote(&challenge.nonce),
+            quote(uri),
+            algorithm.as_param(),
+            response
+        );
+        if let Some(opaque) = &challenge.opaque {
+            header.push_str(&format!(", opaque=\"{}\"", quote(opaque)));
+        }
+        if challenge.qop_auth {
+            header.push_str(&format!(", qop=auth, nc={}, cnonce=\"{}\"", nc, cnonce));
+        }
+        header
+    }
+}
+
+/// Splits `key=value, key="quoted, value"` auth parameters.
+fn parse_params(input: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| *c == ',' || c.is_whitespace()) {
+            chars.next();
+        }
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| *c != '=' && *c != ',') {
+            key.push(c);
+        }
+        if key.is_empty() {
+            break;
+        }
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next()),
+                        '"' => break,
+                        _ => value.push(c),
+                    }
+                }
+            } else {
+                while let Some(c) = chars.next_if(|c| *c != ',') {
+                    value.push(c);
+                }
+            }
+        }
+        params.push((key.trim().to_string(), value.trim().to_string()));
+    }
+
+    params
+}
+
+fn quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Header sent for the first use of `challenge`'s nonce with a fixed `cnonce`.
+    fn authorization(challenge: &str, username: &str, password: &str, cnonce: &str) -> String {
+        let mut session = DigestSession::new(DigestChallenge::parse(challenge).unwrap());
+        session.nonce_count = 1;
+        let request = Request::get("http://example.org/dir/index.html").body(HyperBody::empty()).unwrap();
+        session.authorization(&request, username, password, cnonce)
+    }
+
+    #[test]
+    fn rfc2617_md5_vector() {
+        let header = authorization(
+            "Digest realm=\"testrealm@host.com\", qop=\"auth,auth-int\", \
+             nonce=\"dcd98b7102dd2f0e8b11d0f600bfb0c093\", opaque=\"5ccc069c403ebaf9f0171e9517f40e41\"",
+            "Mufasa",
+            "Circle Of Life",
+            "0a4f113b",
+        );
+        assert_eq!(
+            header,
+            "Digest username=\"Mufasa\", realm=\"testrealm@host.com\", \
+             nonce=\"dcd98b7102dd2f0e8b11d0f600bfb0c093\", uri=\"/dir/index.html\", algorithm=MD5, \
+             response=\"6629fae49393a05397450978507c4ef1\", opaque=\"5ccc069c403ebaf9f0171e9517f40e41\", \
+             qop=auth, nc=00000001, cnonce=\"0a4f113b\""
+        );
+    }
+
+    #[test]
+    fn rfc7616_sha256_vector() {
+        let header = authorization(
+            "Digest realm=\"http-auth@example.org\", qop=\"auth, auth-int\", algorithm=SHA-256, \
+             nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+             opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"",
+            "Mufasa",
+            "Circle of Life",
+            "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ",
+        );
+        assert!(header.contains("algorithm=SHA-256"));
+        assert!(header.contains("response=\"753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1\""));
+    }
+
+    #[test]
+    fn params_keep_quoted_commas_and_escapes() {
+        assert_eq!(
+            parse_params(r#"realm="a, \"b\"", nonce=n1,qop="auth" , stale=TRUE"#),
+            [
+                ("realm".to_string(), "a, \"b\"".to_string()),
+                ("nonce".to_string(), "n1".to_string()),
+                ("qop".to_string(), "auth".to_string()),
+                ("stale".to_string(), "TRUE".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn stale_challenges_are_flagged() {
+        let challenge = DigestChallenge::parse("Digest realm=\"r\", nonce=\"n\", qop=\"auth\", stale=true").unwrap();
+        assert!(challenge.stale);
+        let challenge = DigestChallenge::parse("Digest realm=\"r\", nonce=\"n\", stale=false").unwrap();
+        assert!(!challenge.stale);
+        assert!(!challenge.qop_auth);
+    }
+
+    #[test]
+    fn unsupported_challenges_are_ignored() {
+        assert!(DigestChallenge::parse("Digest realm=\"r\", nonce=\"n\", qop=\"auth-int\"").is_none());
+        assert!(DigestChallenge::parse("Digest realm=\"r\", nonce=\"n\", algorithm=SHA-512-256").is_none());
+        assert!(DigestChallenge::parse("Basic realm=\"r\"").is_none());
+
+        let mut headers = HeaderMap::new();
+        headers.append(WWW_AUTHENTICATE, HeaderValue::from_static("Digest realm=\"r\", nonce=\"a\", algorithm=SHA-512-256"));
+        headers.append(WWW_AUTHENTICATE, HeaderValue::from_static("Digest realm=\"r\", nonce=\"b\", algorithm=MD5"));
+        let challenge = DigestChallenge::from_headers(&headers).unwrap();
+        assert_eq!(challenge.nonce, "b");
+    }
+
+    #[test]
+    fn rfc2069_challenges_answer_without_qop() {
+        let header = authorization("Digest realm=\"r\", nonce=\"n\"", "u", "p", "c");
+        assert!(!header.contains("qop="));
+        assert!(!header.contains("cnonce="));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::client::connector::TlsHandshakeError;
+use hyper::http;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RequestErrorKind {
+    DnsFailure,
+    ConnectionRefused,
+    ConnectionReset,
+    ConnectionClosed,
+    Tls,
+    Timeout,
+    BodyRead,
+    InvalidUrl,
+    InvalidHeader,
+    InvalidBody,
+    Protocol,
+    Auth,
+    Other,
+}
+
+impl RequestErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestErrorKind::DnsFailure => "dns_failure",
+            RequestErrorKind::ConnectionRefused => "connection_refused",
+            RequestErrorKind::ConnectionReset => "connection_reset",
+            RequestErrorKind::ConnectionClosed => "connection_closed",
+            RequestErrorKind::Tls => "tls",
+            RequestErrorKind::Timeout => "timeout",
+            RequestErrorKind::BodyRead => "body_read",
+            RequestErrorKind::InvalidUrl => "invalid_url",
+            RequestErrorKind::InvalidHeader => "invalid_header",
+            RequestErrorKind::InvalidBody => "invalid_body",
+            RequestErrorKind::Protocol => "protocol",
+            RequestErrorKind::Auth => "auth",
+            RequestErrorKind::Other => "other",
+        }
+    }
+}
+
+/// A failed request. `duration` is `None` when the request never reached the
+/// network (bad URL, header or body), so it must not count toward latency.
+#[derive(Debug, Clone)]
+pub struct RequestError {
+    pub kind: RequestErrorKind,
+    pub message: String,
+    pub duration: Option<u128>,
+}
+
+impl RequestError {
+    pub fn new(kind: RequestErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            duration: None,
+        }
+    }
+
+    pub fn from_http(err: http::Error) -> Self {
+        let kind = if err.is::<http::uri::InvalidUri>() || err.is::<http::uri::InvalidUriParts>() {
+            RequestErrorKind::InvalidUrl
+        } else if err.is::<http::header::InvalidHeaderName>()
+            || err.is::<http::header::InvalidHeaderValue>()
+        {
+            RequestErrorKind::InvalidHeader
+        } else {
+            RequestErrorKind::Other
+        };
+        Self::new(kind, err.to_string())
+    }
+
+    pub fn from_hyper(err: hyper::Error, duration: u128) -> Self {
+        let kind = classify_hyper(&err);
+        let mut message = err.to_string();
+        if let Some(source) = err.source() {
+            let cause = source.to_string();
+            if !message.contains(&cause) {
+                message = format!("{}: {}", message, cause);
+            }
+        }
+        Self {
+            kind,
+            message,
+            duration: Some(duration),
+        }
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind.as_str(), self.message)
+    }
+}
+
+fn classify_hyper(err: &hyper::Error) -> RequestErrorKind {
+    if find_source::<TlsHandshakeError>(err).is_some() {
+        return RequestErrorKind::Tls;
+    }
+
+    if let Some(io_err) = find_source::<io::Error>(err) {
+        match io_err.kind() {
+            io::ErrorKind::ConnectionRefused => return RequestErrorKind::ConnectionRefused,
+            io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe => return RequestErrorKind::ConnectionReset,
+            io::ErrorKind::TimedOut => return RequestErrorKind::Timeout,
+            _ => {}
+        }
+    }
+
+    // hyper's connector error type is private; it only exposes "dns error" in its message.
+    let mut current: Option<&(dyn StdError + 'static)> = Some(err);
+    while let Some(e) = current {
+        if e.to_string().starts_with("dns error") {
+            return RequestErrorKind::DnsFailure;
+        }
+        current = e.source();
+    }
+
+    if err.is_timeout() {
+        RequestErrorKind::Timeout
+    } else if err.is_closed() || err.is_incomplete_message() || err.is_canceled() {
+        RequestErrorKind::ConnectionClosed
+    } else if err.is_parse() || err.is_parse_status() {
+        RequestErrorKind::Protocol
+    } else {
+        RequestErrorKind::Other
+    }
+}
+
+fn find_source<'a, T: StdError + 'static>(err: &'a (dyn StdError + 'static)) -> Option<&'a T> {
+    let mut current = Some(err);
+    while let Some(e) = current {
+        if let Some(found) = e.downcast_ref::<T>() {
+            return Some(found);
+        }
+        current = e.source();
+    }
+    None
+}
@@ -0,0 +1,123 @@
+use crate::client::error::{RequestError, RequestErrorKind};
+use crate::models::dsl_model::{Auth, JwtAlgorithm, JwtIssuePolicy};
+use crate::template::{render_json, TemplateContext};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Tokens are re-signed this long before they actually expire.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(10);
+
+/// Signing key and claim set of an `Auth::Jwt`, shared by all virtual users.
+pub struct JwtSigner {
+    header: Header,
+    key: EncodingKey,
+    claims: Map<String, Value>,
+    expires_in: u64,
+    issue_per: JwtIssuePolicy,
+}
+
+impl JwtSigner {
+    pub fn new(auth: &Auth) -> Result<Self, RequestError> {
+        let Auth::Jwt {
+            algorithm,
+            secret,
+            private_key_pem,
+            key_id,
+            claims,
+            expires_in_secs,
+            issue_per,
+        } = auth
+        else {
+            return Err(RequestError::new(RequestErrorKind::Auth, "Auth is not Jwt"));
+        };
+
+        let auth_error = |message: String| RequestError::new(RequestErrorKind::Auth, message);
+        let (algorithm, key) = match algorithm {
+            JwtAlgorithm::HS256 => {
+                let secret = secret.as_ref().ok_or_else(|| auth_error("HS256 requires `secret`".to_string()))?;
+                (Algorithm::HS256, EncodingKey::from_secret(secret.as_bytes()))
+            }
+            JwtAlgorithm::RS256 => {
+                let pem = private_key_pem
+                    .as_ref()
+                    .ok_or_else(|| auth_error("RS256 requires `private_key_pem`".to_string()))?;
+                let key = EncodingKey::from_rsa_pem(pem.as_bytes())
+                    .map_err(|e| auth_error(format!("Invalid RSA private key: {}", e)))?;
+                (Algorithm::RS256, key)
+            }
+            JwtAlgorithm::ES256 => {
+                let pem = private_key_pem
+                    .as_ref()
+                    .ok_or_else(|| auth_error("ES256 requires `private_key_pem`".to_string()))?;
+                let key = EncodingKey::from_ec_pem(pem.as_bytes())
+                    .map_err(|e| auth_error(format!("Invalid EC private key: {}", e)))?;
+                (Algorithm::ES256, key)
+            }
+        };
+
+        let mut header = Header::new(algorithm);
+        header.kid = key_id.clone();
+
+        let signer = Self {
+            header,
+            key,
+            claims: claims.clone(),
+            expires_in: *expires_in_secs,
+            issue_per: *issue_per,
+        };
+        // Fail before the test starts if the key can't actually sign.
+        signer.sign(&TemplateContext {
+            vu_id: 1,
+            ..Default::default()
+        })?;
+        Ok(signer)
+    }
+
+    fn sign(&self, ctx: &TemplateContext) -> Result<String, RequestError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let mut claims = Map::new();
+        for (name, value) in &self.claims {
+            claims.insert(name.clone(), render_json(value, ctx));
+        }
+        claims.entry("iat").or_insert(Value::from(now));
+        claims.entry("exp").or_insert(Value::from(now + self.expires_in));
+
+        jsonwebtoken::encode(&self.header, &claims, &self.key)
+            .map_err(|e| RequestError::new(RequestErrorKind::Auth, format!("Failed to sign JWT: {}", e)))
+    }
+}
+
+/// JWT state of one virtual user.
+pub struct JwtSession {
+    signer: Arc<JwtSigner>,
+    current: Option<(String, Instant)>,
+}
+
+impl JwtSession {
+    pub fn new(signer: Arc<JwtSigner>) -> Self {
+        Self { signer, current: None }
+    }
+
+    /// Returns the token for the next request, signing a new one when the
+    /// policy asks for it or the previous one is about to expire.
+    pub fn token(&mut self, ctx: &TemplateContext) -> Result<String, RequestError> {
+        if self.signer.issue_per == JwtIssuePolicy::User
+            && let Some((token, expires_at)) = &self.current
+            && Instant::now() + EXPIRY_MARGIN < *expires_at
+        {
+            return Ok(token.clone());
+        }
+
+        let token = self.signer.sign(ctx)?;
+        let expires_at = Instant::now() + Duration::from_secs(self.signer.expires_in);
+        self.current = Some((token.clone(), expires_at));
+        Ok(token)
+    }
+}
+
@@ -1,36 +1,130 @@
 use hyper::{Client, Request, Body as HyperBody, Method, Uri, StatusCode};
-use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
-use hyper_tls::HttpsConnector;
-use crate::models::dsl_model::{DslConfig, Body, Auth, HttpMethod};
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::{HeaderMap, HeaderValue, ACCEPT_ENCODING, AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE, HOST};
+use crate::models::dsl_model::{DslConfig, Auth, HttpMethod, ConnectionPolicy, ResponseBodyMode, DataRow};
+use crate::template::{render, TemplateContext};
 use base64::engine::general_purpose::STANDARD as BASE64;
 use base64::Engine;
 use url::Url;
-use std::time::Instant;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
-pub type HttpsClient = Client<HttpsConnector<hyper::client::HttpConnector>>;
+pub mod body;
+pub mod compression;
+pub mod connector;
+pub mod cookies;
+pub mod digest;
+pub mod error;
+pub mod jwt;
+pub mod oauth;
+pub mod redirect;
+pub mod signing;
 
-pub async fn send_request(
-    client: &HttpsClient,
-    config: &DslConfig,
-) -> Result<(StatusCode, u128), (String, u128)> {
-    let mut url = Url::parse(&config.target).map_err(|e| (e.to_string(), 0))?;
+use body::encode_body;
+use compression::{compress, Decoder};
+use connector::{CountingConnector, TlsConnector};
+use cookies::{add_cookie_header, store_cookies};
+use cookie_store::CookieStore;
+use digest::{DigestChallenge, DigestSession};
+use error::{RequestError, RequestErrorKind};
+use jwt::JwtSession;
+use oauth::OAuthTokenCache;
+use redirect::{prepare_redirect, redirect_method, redirect_target};
+use signing::{resign_redirect, sign_request, signs_requests};
+use chrono::Utc;
+
+pub type HttpsClient = Client<CountingConnector<TlsConnector>>;
+
+/// State a virtual user carries from one request to the next.
+#[derive(Default)]
+pub struct Session {
+    /// 1-based index of the virtual user.
+    pub vu_id: usize,
+    /// 0-based iteration of the virtual user.
+    pub iteration: u64,
+    /// Test-wide request sequence shared by all virtual users.
+    pub counter: Arc<AtomicU64>,
+    pub oauth: Option<Arc<OAuthTokenCache>>,
+    /// Last Digest challenge received, reused until the server replaces it.
+    pub digest: Option<DigestSession>,
+    pub jwt: Option<JwtSession>,
+    pub cookies: Option<CookieStore>,
+    /// Dataset row used by the current iteration.
+    pub row: Option<DataRow>,
+    /// Values extracted by the setup phase.
+    pub vars: Option<Arc<HashMap<String, String>>>,
+}
+
+#[derive(Debug)]
+pub struct ResponseInfo {
+    pub status: StatusCode,
+    /// Request line, headers and body as sent by the worker.
+    pub bytes_sent: u64,
+    /// Request body size before compression.
+    pub request_body_bytes: u64,
+    pub bytes_received: u64,
+    /// Response body size after decompression; equal to `bytes_received` when uncompressed.
+    pub bytes_decoded: u64,
+    /// Redirects followed before the final response.
+    pub redirects: u32,
+    /// Time to first byte (response headers received), in ms.
+    pub ttfb: u128,
+    /// Time to last byte, in ms.
+    pub duration: u128,
+    /// Response headers and decoded body, kept only in `ResponseBodyMode::Buffer`.
+    pub headers: Option<HeaderMap>,
+    pub body: Option<Bytes>,
+}
+
+pub fn build_client(config: &DslConfig, tls: TlsConnector, opened: Arc<AtomicU64>) -> HttpsClient {
+    let connector = CountingConnector::new(tls, opened);
+    let mut builder = Client::builder();
+
+    if let Some(conn) = &config.connection {
+        if conn.policy == ConnectionPolicy::NoKeepAlive {
+            builder.pool_max_idle_per_host(0);
+        } else if let Some(max_idle) = conn.pool_max_idle_per_host {
+            builder.pool_max_idle_per_host(max_idle);
+        }
+
+        if let Some(idle_secs) = conn.pool_idle_timeout_secs {
+            builder.pool_idle_timeout(Duration::from_secs(idle_secs));
+        }
+    }
+
+    builder.build::<_, HyperBody>(connector)
+}
+
+pub fn build_request(config: &DslConfig, ctx: &TemplateContext) -> Result<Request<HyperBody>, RequestError> {
+    let target = render(&config.target, ctx);
+    let mut url = Url::parse(&target)
+        .map_err(|e| RequestError::new(RequestErrorKind::InvalidUrl, format!("{}: {}", target, e)))?;
+
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(RequestError::new(
+            RequestErrorKind::InvalidUrl,
+            format!("Unsupported URL scheme: {}", url.scheme()),
+        ));
+    }
 
     if let Some(params) = &config.query_params {
         let mut pairs = url.query_pairs_mut();
         for (key, value) in params {
-            pairs.append_pair(key, value);
+            pairs.append_pair(&render(key, ctx), &render(value, ctx));
         }
     }
 
-    if let Some(Auth::ApiKey { key_name, key_value, add_to }) = &config.auth {
-        if add_to == "query" {
-            url.query_pairs_mut().append_pair(key_name, key_value);
-        }
+    if let Some(Auth::ApiKey { key_name, key_value, add_to }) = &config.auth
+        && add_to == "query"
+    {
+        url.query_pairs_mut().append_pair(key_name, key_value);
     }
 
     let uri: Uri = url.as_str()
         .parse::<Uri>()
-        .map_err(|e| (e.to_string(), 0))?;
+        .map_err(|e| RequestError::new(RequestErrorKind::InvalidUrl, e.to_string()))?;
 
     let method = match config.method {
         HttpMethod::GET     => Method::GET,
@@ -42,27 +136,37 @@ pub async fn send_request(
         HttpMethod::OPTIONS => Method::OPTIONS,
     };
 
-    let body = match &config.body {
-        Some(Body::Json(json)) => {
-            let json_string = serde_json::to_string(json).map_err(|e| (e.to_string(), 0))?;
-            HyperBody::from(json_string)
-        }
-        Some(Body::Xml(xml)) => HyperBody::from(xml.clone()),
-        None => HyperBody::empty(),
-    };
+    let encoded = config.body.as_ref().map(|body| encode_body(body, ctx)).transpose()?;
 
     let mut req_builder = Request::builder()
         .method(method)
         .uri(uri.clone());
 
-    match &config.body {
-        Some(Body::Json(_)) => {
-            req_builder = req_builder.header(CONTENT_TYPE, "application/json");
-        }
-        Some(Body::Xml(_)) => {
-            req_builder = req_builder.header(CONTENT_TYPE, "application/xml");
+    let compression = config.compression.as_ref();
+
+    let wire_body = match encoded {
+        Some(encoded) => {
+            req_builder = req_builder
+                .header(CONTENT_TYPE, encoded.content_type)
+                .extension(UncompressedBodySize(encoded.bytes.len() as u64));
+            match compression.and_then(|c| c.request) {
+                Some(encoding) => {
+                    let compressed = compress(encoding, &encoded.bytes)
+                        .map_err(|e| RequestError::new(RequestErrorKind::InvalidBody, e.to_string()))?;
+                    req_builder = req_builder.header(CONTENT_ENCODING, encoding.as_header());
+                    Bytes::from(compressed)
+                }
+                None => encoded.bytes,
+            }
         }
-        None => {}
+        None => Bytes::new(),
+    };
+
+    if let Some(compression) = compression
+        && !compression.accept.is_empty()
+    {
+        let accepted: Vec<&str> = compression.accept.iter().map(|e| e.as_header()).collect();
+        req_builder = req_builder.header(ACCEPT_ENCODING, accepted.join(", "));
     }
 
     if let Some(auth) = &config.auth {
@@ -83,29 +187,348 @@ pub async fn send_request(
 
     if let Some(custom_headers) = &config.headers {
         for (key, value) in custom_headers {
-            req_builder = req_builder.header(key.as_str(), value.as_str());
+            req_builder = req_builder.header(key.as_str(), render(value, ctx).as_ref());
         }
     }
 
-    let request = req_builder.body(body).map_err(|e| (e.to_string(), 0))?;
+    let mut request = req_builder
+        .body(HyperBody::from(wire_body.clone()))
+        .map_err(RequestError::from_http)?;
+
+    if let Some(auth) = &config.auth {
+        sign_request(&mut request, auth, &wire_body, Utc::now())?;
+    }
+    request.extensions_mut().insert(WireBody(wire_body));
+
+    Ok(request)
+}
+
+pub async fn send_request(
+    client: &HttpsClient,
+    config: &DslConfig,
+    session: &mut Session,
+) -> Result<ResponseInfo, RequestError> {
+    let ctx = TemplateContext {
+        vu_id: session.vu_id,
+        iteration: session.iteration,
+        counter: session.counter.fetch_add(1, Ordering::Relaxed) + 1,
+        row: session.row.as_deref(),
+        vars: session.vars.as_deref(),
+    };
+    // Rendered once: a Digest retry or a redirect hop resends these same parts.
+    let template = build_request(config, &ctx)?;
+    let mut request = copy_request(&template);
+
+    let oauth_token = match (&config.auth, &session.oauth) {
+        (Some(auth @ Auth::OAuth2 { .. }), Some(cache)) => {
+            let token = cache.access_token(client, auth).await?;
+            let value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|e| RequestError::new(RequestErrorKind::Auth, format!("Invalid access token: {}", e)))?;
+            request.headers_mut().insert(AUTHORIZATION, value);
+            Some(token)
+        }
+        _ => None,
+    };
+    if let Some(jwt) = session.jwt.as_mut() {
+        let token = jwt.token(&ctx)?;
+        let value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|e| RequestError::new(RequestErrorKind::Auth, format!("Invalid JWT: {}", e)))?;
+        request.headers_mut().insert(AUTHORIZATION, value);
+    }
+
+    let digest_credentials = match &config.auth {
+        Some(Auth::Digest { username, password }) => Some((username.as_str(), password.as_str())),
+        _ => None,
+    };
+    if let (Some((username, password)), Some(digest)) = (digest_credentials, session.digest.as_mut()) {
+        digest.authorize(&mut request, username, password)?;
+    }
+
+    let mut current_url = Url::parse(&request.uri().to_string())
+        .map_err(|e| RequestError::new(RequestErrorKind::InvalidUrl, e.to_string()))?;
+    if let Some(jar) = &session.cookies {
+        add_cookie_header(jar, &current_url, &mut request);
+    }
+
+    let mut bytes_sent = request_size(&request);
+    let request_body_bytes = request
+        .extensions()
+        .get::<UncompressedBodySize>()
+        .map(|size| size.0)
+        .unwrap_or(0);
+    let mut method = request.method().clone();
+    // A signed `Authorization` only fits the first request; hops get their own signature.
+    let signed = config.auth.as_ref().is_some_and(signs_requests);
+    let authorization = request.headers().get(AUTHORIZATION).filter(|_| !signed).cloned();
 
     let start = Instant::now();
-    let response = client.request(request).await;
-    let duration = start.elapsed().as_millis();
-
-    match response {
-        Ok(resp) => Ok((resp.status(), duration)),
-        Err(e) => {
-            let msg = if e.is_connect() {
-                "Connection refused or host unreachable"
-            } else if e.is_timeout() {
-                "Timeout"
-            } else if e.is_closed() {
-                "Connection closed unexpectedly"
-            } else {
-                "Unknown network error"
+    let mut response = client
+        .request(request)
+        .await
+        .map_err(|e| RequestError::from_hyper(e, start.elapsed().as_millis()))?;
+    if let Some(jar) = session.cookies.as_mut() {
+        store_cookies(jar, &current_url, response.headers());
+    }
+
+    let body_error = |message: String| RequestError {
+        kind: RequestErrorKind::BodyRead,
+        message,
+        duration: Some(start.elapsed().as_millis()),
+    };
+    let mut bytes_received = 0u64;
+
+    // Digest: answer the challenge once when the request went out without
+    // credentials or with a stale nonce. The challenge round trip counts
+    // toward this request's duration and traffic.
+    if let Some((username, password)) = digest_credentials
+        && response.status() == StatusCode::UNAUTHORIZED
+        && let Some(challenge) = DigestChallenge::from_headers(response.headers())
+    {
+        let retry = session.digest.is_none() || challenge.stale;
+        session.digest = Some(DigestSession::new(challenge));
+
+        if retry && let Some(digest) = session.digest.as_mut() {
+            bytes_received += discard_body(response.into_body())
+                .await
+                .map_err(|e| body_error(format!("Failed to read Digest challenge: {}", e)))?;
+
+            let mut request = copy_request(&template);
+            digest.authorize(&mut request, username, password)?;
+            if let Some(jar) = &session.cookies {
+                add_cookie_header(jar, &current_url, &mut request);
+            }
+            bytes_sent += request_size(&request);
+            response = client
+                .request(request)
+                .await
+                .map_err(|e| RequestError::from_hyper(e, start.elapsed().as_millis()))?;
+            if let Some(jar) = session.cookies.as_mut() {
+                store_cookies(jar, &current_url, response.headers());
+            }
+        }
+    }
+
+    // Redirect chains are timed and counted as a single request.
+    let mut redirects = 0u32;
+    if let Some(redirect_config) = &config.redirects {
+        let mut with_body = true;
+        while let Some(next_url) =
+            redirect_target(redirect_config, redirects, &current_url, response.status(), response.headers())
+        {
+            let (next_method, keeps_body) = redirect_method(&method, response.status());
+            method = next_method;
+            with_body &= keeps_body;
+
+            bytes_received += discard_body(response.into_body())
+                .await
+                .map_err(|e| body_error(format!("Failed to read redirect response: {}", e)))?;
+
+            let mut request = copy_request(&template);
+            let body = match request.extensions().get::<WireBody>() {
+                Some(body) if with_body => body.0.clone(),
+                _ => Bytes::new(),
             };
-            Err((msg.to_string(), duration))
+            prepare_redirect(&mut request, &method, with_body, &current_url, &next_url, authorization.as_ref())?;
+            if let Some(auth) = config.auth.as_ref().filter(|auth| signs_requests(auth)) {
+                let same_host = next_url.host_str() == current_url.host_str();
+                resign_redirect(&mut request, auth, &body, same_host, Utc::now())?;
+            }
+            current_url = next_url;
+            if let Some(jar) = &session.cookies {
+                add_cookie_header(jar, &current_url, &mut request);
+            }
+            bytes_sent += request_size(&request);
+            response = client
+                .request(request)
+                .await
+                .map_err(|e| RequestError::from_hyper(e, start.elapsed().as_millis()))?;
+            if let Some(jar) = session.cookies.as_mut() {
+                store_cookies(jar, &current_url, response.headers());
+            }
+            redirects += 1;
+        }
+    }
+    let ttfb = start.elapsed().as_millis();
+
+    let status = response.status();
+    if status == StatusCode::UNAUTHORIZED
+        && let (Some(cache), Some(token)) = (&session.oauth, &oauth_token)
+    {
+        cache.invalidate(token).await;
+    }
+
+    let buffer = config.response_body == ResponseBodyMode::Buffer;
+    let accepted = config.compression.as_ref().map(|c| c.accept.as_slice()).unwrap_or(&[]);
+    let content_encoding = response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok());
+    let mut decoder = Decoder::for_response(content_encoding, accepted, buffer);
+    let headers = buffer.then(|| response.headers().clone());
+
+    let mut body = response.into_body();
+    let mut collected = Vec::new();
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| {
+            let mut err = RequestError::from_hyper(e, start.elapsed().as_millis());
+            err.kind = RequestErrorKind::BodyRead;
+            err
+        })?;
+        bytes_received += chunk.len() as u64;
+        if let Some(decoder) = decoder.as_mut() {
+            decoder
+                .write(&chunk)
+                .map_err(|e| body_error(format!("Failed to decompress response: {}", e)))?;
+        } else if buffer {
+            collected.extend_from_slice(&chunk);
+        }
+    }
+
+    let (bytes_decoded, body) = match decoder {
+        Some(decoder) => decoder
+            .finish()
+            .map_err(|e| body_error(format!("Failed to decompress response: {}", e)))?,
+        None => (bytes_received, buffer.then_some(collected)),
+    };
+
+    Ok(ResponseInfo {
+        status,
+        bytes_sent,
+        request_body_bytes,
+        bytes_received,
+        bytes_decoded,
+        redirects,
+        ttfb,
+        duration: start.elapsed().as_millis(),
+        headers,
+        body: body.map(Bytes::from),
+    })
+}
+
+/// Reads and drops the body of an intermediate response, returning its size.
+async fn discard_body(mut body: HyperBody) -> Result<u64, hyper::Error> {
+    let mut size = 0u64;
+    while let Some(chunk) = body.data().await {
+        size += chunk?.len() as u64;
+    }
+    Ok(size)
+}
+
+/// Fresh copy of a request built by `build_request`, with its body, so it can
+/// be sent again without rendering the templates a second time.
+fn copy_request(request: &Request<HyperBody>) -> Request<HyperBody> {
+    let body = request
+        .extensions()
+        .get::<WireBody>()
+        .map(|body| body.0.clone())
+        .unwrap_or_default();
+    let mut copy = Request::new(HyperBody::from(body.clone()));
+    *copy.method_mut() = request.method().clone();
+    *copy.uri_mut() = request.uri().clone();
+    *copy.version_mut() = request.version();
+    *copy.headers_mut() = request.headers().clone();
+    copy.extensions_mut().insert(WireBody(body));
+    if let Some(size) = request.extensions().get::<UncompressedBodySize>() {
+        copy.extensions_mut().insert(*size);
+    }
+    copy
+}
+
+/// Body size before compression, attached to requests built by `build_request`.
+#[derive(Clone, Copy)]
+struct UncompressedBodySize(u64);
+
+/// Body bytes as sent, attached to requests built by `build_request` so
+/// redirect hops can be signed again.
+#[derive(Clone)]
+struct WireBody(Bytes);
+
+/// Approximate wire size of an HTTP/1.1 request, including the `Host` and
+/// `Content-Length` headers that hyper adds on its own. A `Host` set
+/// explicitly, as SigV4 signing does, is counted once among the headers.
+fn request_size(request: &Request<HyperBody>) -> u64 {
+    let uri = request.uri();
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let body_len = request.body().size_hint().exact().unwrap_or(0);
+
+    let mut size = request.method().as_str().len() + 1 + path.len() + " HTTP/1.1\r\n".len();
+    if let Some(authority) = uri.authority().filter(|_| !request.headers().contains_key(HOST)) {
+        size += "host: \r\n".len() + authority.as_str().len();
+    }
+    for (name, value) in request.headers() {
+        size += name.as_str().len() + 2 + value.len() + 2;
+    }
+    if body_len > 0 {
+        size += "content-length: \r\n".len() + body_len.to_string().len();
+    }
+    size += 2;
+
+    size as u64 + body_len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(extra: serde_json::Value) -> DslConfig {
+        let mut config = serde_json::json!({
+            "request_id": "r",
+            "name": "n",
+            "target": "http://h/{{uuid()}}",
+            "method": "POST",
+            "concurrency": 1,
+            "duration": 1,
+        });
+        config.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(config).unwrap()
+    }
+
+    fn wire_body(request: &Request<HyperBody>) -> Bytes {
+        request.extensions().get::<WireBody>().unwrap().0.clone()
+    }
+
+    #[test]
+    fn copies_resend_the_rendered_request() {
+        let config = config(serde_json::json!({
+            "headers": { "Idempotency-Key": "{{uuid()}}" },
+            "body": { "type": "Json", "content": { "id": "{{random_string(12)}}" } },
+        }));
+        let template = build_request(&config, &TemplateContext::default()).unwrap();
+        let first = copy_request(&template);
+        let second = copy_request(&template);
+
+        for copy in [&first, &second] {
+            assert_eq!(copy.method(), template.method());
+            assert_eq!(copy.uri(), template.uri());
+            assert_eq!(copy.headers(), template.headers());
+            assert_eq!(wire_body(copy), wire_body(&template));
+            assert_eq!(copy.body().size_hint().exact(), Some(wire_body(&template).len() as u64));
         }
+        // A second render would have produced new values.
+        let rebuilt = build_request(&config, &TemplateContext::default()).unwrap();
+        assert_ne!(rebuilt.uri(), template.uri());
+    }
+
+    #[test]
+    fn explicit_host_is_counted_once() {
+        let request = Request::get("http://example.com/a").body(HyperBody::empty()).unwrap();
+        let implicit = request_size(&request);
+        assert_eq!(implicit as usize, "GET /a HTTP/1.1\r\nhost: example.com\r\n\r\n".len());
+
+        let signed = config(serde_json::json!({
+            "target": "http://example.com/a",
+            "method": "GET",
+            "auth": { "type": "AwsSigV4", "credentials": {
+                "access_key": "AKIDEXAMPLE", "secret_key": "secret", "region": "us-east-1", "service": "s3",
+            } },
+        }));
+        let request = build_request(&signed, &TemplateContext::default()).unwrap();
+        let headers: usize = request
+            .headers()
+            .iter()
+            .map(|(name, value)| name.as_str().len() + 2 + value.len() + 2)
+            .sum();
+        assert_eq!(request_size(&request) as usize, "GET /a HTTP/1.1\r\n\r\n".len() + headers);
     }
 }
@@ -0,0 +1,174 @@
+use crate::client::error::{RequestError, RequestErrorKind};
+use crate::client::HttpsClient;
+use crate::models::dsl_model::{Auth, OAuthGrant};
+use hyper::header::{ACCEPT, CONTENT_TYPE};
+use hyper::{Body as HyperBody, Method, Request};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use url::form_urlencoded;
+
+/// Tokens are refreshed this long before they actually expire.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(10);
+
+struct CachedToken {
+    access_token: String,
+    expires_at: Option<Instant>,
+}
+
+impl CachedToken {
+    fn is_fresh(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => Instant::now() + EXPIRY_MARGIN < expires_at,
+            None => true,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+/// OAuth2 access token shared by all virtual users of a test. Token requests
+/// are counted here instead of in the test's `Metrics`.
+#[derive(Default)]
+pub struct OAuthTokenCache {
+    current: Mutex<Option<CachedToken>>,
+    pub requests: AtomicU64,
+    pub failures: AtomicU64,
+    pub total_duration_ms: AtomicU64,
+}
+
+impl OAuthTokenCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a valid access token, fetching a new one if none is cached or it expired.
+    pub async fn access_token(&self, client: &HttpsClient, auth: &Auth) -> Result<String, RequestError> {
+        let mut current = self.current.lock().await;
+        if let Some(token) = current.as_ref().filter(|t| t.is_fresh()) {
+            return Ok(token.access_token.clone());
+        }
+
+        let start = Instant::now();
+        let fetched = fetch_token(client, auth).await;
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.total_duration_ms
+            .fetch_add(start.elapsed().as_millis() as u64, Ordering::Relaxed);
+
+        match fetched {
+            Ok(token) => {
+                let access_token = token.access_token.clone();
+                *current = Some(token);
+                Ok(access_token)
+            }
+            Err(err) => {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                Err(err)
+            }
+        }
+    }
+
+    /// Drops the cached token after the server rejected it, unless another
+    /// virtual user already replaced it.
+    pub async fn invalidate(&self, rejected: &str) {
+        let mut current = self.current.lock().await;
+        if current.as_ref().is_some_and(|t| t.access_token == rejected) {
+            *current = None;
+        }
+    }
+
+    pub fn average_duration_ms(&self) -> f64 {
+        let requests = self.requests.load(Ordering::Relaxed);
+        if requests == 0 {
+            return 0.0;
+        }
+        self.total_duration_ms.load(Ordering::Relaxed) as f64 / requests as f64
+    }
+}
+
+async fn fetch_token(client: &HttpsClient, auth: &Auth) -> Result<CachedToken, RequestError> {
+    let Auth::OAuth2 { token_url, .. } = auth else {
+        return Err(RequestError::new(RequestErrorKind::Auth, "Auth is not OAuth2"));
+    };
+    let form = token_form(auth)?;
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(token_url.as_str())
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .header(ACCEPT, "application/json")
+        .body(HyperBody::from(form))
+        .map_err(|e| RequestError::new(RequestErrorKind::Auth, format!("Invalid token request: {}", e)))?;
+
+    let auth_error = |message: String| RequestError::new(RequestErrorKind::Auth, message);
+
+    let response = client
+        .request(request)
+        .await
+        .map_err(|e| auth_error(format!("Token request failed: {}", e)))?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| auth_error(format!("Failed to read token response: {}", e)))?;
+
+    if !status.is_success() {
+        let snippet: String = String::from_utf8_lossy(&body).chars().take(200).collect();
+        return Err(auth_error(format!("Token endpoint returned {}: {}", status, snippet)));
+    }
+
+    let token: TokenResponse = serde_json::from_slice(&body)
+        .map_err(|e| auth_error(format!("Invalid token response: {}", e)))?;
+
+    Ok(CachedToken {
+        access_token: token.access_token,
+        expires_at: token.expires_in.map(|secs| Instant::now() + Duration::from_secs(secs)),
+    })
+}
+
+fn token_form(auth: &Auth) -> Result<String, RequestError> {
+    let Auth::OAuth2 {
+        grant_type,
+        client_id,
+        client_secret,
+        username,
+        password,
+        scope,
+        ..
+    } = auth
+    else {
+        return Err(RequestError::new(RequestErrorKind::Auth, "Auth is not OAuth2"));
+    };
+
+    let mut form = form_urlencoded::Serializer::new(String::new());
+    match grant_type {
+        OAuthGrant::ClientCredentials => {
+            form.append_pair("grant_type", "client_credentials");
+        }
+        OAuthGrant::Password => {
+            let (Some(username), Some(password)) = (username, password) else {
+                return Err(RequestError::new(
+                    RequestErrorKind::Auth,
+                    "OAuth2 password grant requires username and password",
+                ));
+            };
+            form.append_pair("grant_type", "password");
+            form.append_pair("username", username);
+            form.append_pair("password", password);
+        }
+    }
+    form.append_pair("client_id", client_id);
+    if let Some(secret) = client_secret {
+        form.append_pair("client_secret", secret);
+    }
+    if let Some(scope) = scope {
+        form.append_pair("scope", scope);
+    }
+
+    Ok(form.finish())
+}
@@ -0,0 +1,85 @@
+use crate::client::error::{RequestError, RequestErrorKind};
+use crate::models::dsl_model::{RedirectConfig, RedirectPolicy};
+use hyper::header::{
+    HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, HOST, LOCATION,
+    PROXY_AUTHORIZATION,
+};
+use hyper::{Body as HyperBody, Method, Request, StatusCode, Uri};
+use url::Url;
+
+/// Returns the URL a redirect response points to, if the policy allows
+/// following it after `followed` hops.
+pub fn redirect_target(
+    config: &RedirectConfig,
+    followed: u32,
+    current: &Url,
+    status: StatusCode,
+    headers: &HeaderMap,
+) -> Option<Url> {
+    if config.policy == RedirectPolicy::None || followed >= config.max_redirects {
+        return None;
+    }
+    if !matches!(status.as_u16(), 301 | 302 | 303 | 307 | 308) {
+        return None;
+    }
+
+    let location = headers.get(LOCATION)?.to_str().ok()?;
+    let next = current.join(location).ok()?;
+    if next.scheme() != "http" && next.scheme() != "https" {
+        return None;
+    }
+    if config.policy == RedirectPolicy::SameHost && next.host_str() != current.host_str() {
+        return None;
+    }
+    Some(next)
+}
+
+/// Method of the next hop and whether it still carries the request body.
+/// 307 and 308 repeat both; 301, 302 and 303 switch to a body-less `GET` as
+/// browsers do, except that `HEAD` stays `HEAD`.
+pub fn redirect_method(method: &Method, status: StatusCode) -> (Method, bool) {
+    match status {
+        StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => (method.clone(), true),
+        _ if *method == Method::HEAD => (Method::HEAD, false),
+        _ => (Method::GET, false),
+    }
+}
+
+/// Turns a freshly built request into the request for the next hop.
+/// Credentials are only carried over while the chain stays on the same host.
+pub fn prepare_redirect(
+    request: &mut Request<HyperBody>,
+    method: &Method,
+    with_body: bool,
+    from: &Url,
+    to: &Url,
+    authorization: Option<&HeaderValue>,
+) -> Result<(), RequestError> {
+    *request.uri_mut() = to
+        .as_str()
+        .parse::<Uri>()
+        .map_err(|e| RequestError::new(RequestErrorKind::InvalidUrl, format!("Invalid redirect: {}", e)))?;
+    *request.method_mut() = method.clone();
+
+    let headers = request.headers_mut();
+    headers.remove(HOST);
+    if !with_body {
+        headers.remove(CONTENT_TYPE);
+        headers.remove(CONTENT_ENCODING);
+        headers.remove(CONTENT_LENGTH);
+    }
+    if to.host_str() == from.host_str() {
+        if let Some(authorization) = authorization {
+            headers.insert(AUTHORIZATION, authorization.clone());
+        }
+    } else {
+        headers.remove(AUTHORIZATION);
+        headers.remove(PROXY_AUTHORIZATION);
+        headers.remove(COOKIE);
+    }
+
+    if !with_body {
+        *request.body_mut() = HyperBody::empty();
+    }
+    Ok(())
+}
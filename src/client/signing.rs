@@ -0,0 +1,451 @@
+use crate::client::error::{RequestError, RequestErrorKind};
+use crate::models::dsl_model::{Auth, HmacAlgorithm};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use hyper::header::{HeaderName, HeaderValue, AUTHORIZATION, HOST};
+use hyper::{Body as HyperBody, Request};
+use sha2::{Digest, Sha256, Sha512};
+use url::form_urlencoded;
+
+/// Signs the request in place for `Auth::Hmac` and `Auth::AwsSigV4`; other
+/// auth types are left untouched.
+pub fn sign_request(
+    request: &mut Request<HyperBody>,
+    auth: &Auth,
+    body: &[u8],
+    now: DateTime<Utc>,
+) -> Result<(), RequestError> {
+    match auth {
+        Auth::Hmac {
+            secret,
+            key_id,
+            algorithm,
+            signed_headers,
+            signature_header,
+            timestamp_header,
+            key_id_header,
+        } => {
+            let timestamp = now.timestamp().to_string();
+            insert_header(request, timestamp_header, &timestamp)?;
+            if let Some(key_id) = key_id {
+                insert_header(request, key_id_header, key_id)?;
+            }
+
+            let string_to_sign = hmac_string_to_sign(request, signed_headers, body, &timestamp);
+            let signature = match algorithm {
+                HmacAlgorithm::Sha256 => hex::encode(hmac_sha256(secret.as_bytes(), string_to_sign.as_bytes())),
+                HmacAlgorithm::Sha512 => hex::encode(hmac_sha512(secret.as_bytes(), string_to_sign.as_bytes())),
+            };
+            insert_header(request, signature_header, &signature)
+        }
+        Auth::AwsSigV4 {
+            access_key,
+            secret_key,
+            session_token,
+            region,
+            service,
+        } => {
+            let credentials = AwsCredentials {
+                access_key,
+                secret_key,
+                session_token: session_token.as_deref(),
+                region,
+                service,
+            };
+            sign_aws_v4(request, &credentials, body, now)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether the auth signs each request over its method, URI and body.
+pub fn signs_requests(auth: &Auth) -> bool {
+    matches!(auth, Auth::Hmac { .. } | Auth::AwsSigV4 { .. })
+}
+
+/// Replaces the signature of a request rebuilt for a redirect hop, whose
+/// method, URI and body may differ from the original. Hops to another host
+/// are left unsigned, like `Authorization` is for the other auth types.
+pub fn resign_redirect(
+    request: &mut Request<HyperBody>,
+    auth: &Auth,
+    body: &[u8],
+    same_host: bool,
+    now: DateTime<Utc>,
+) -> Result<(), RequestError> {
+    let headers = request.headers_mut();
+    match auth {
+        Auth::Hmac {
+            signature_header,
+            timestamp_header,
+            key_id_header,
+            ..
+        } => {
+            for name in [signature_header, timestamp_header, key_id_header] {
+                headers.remove(name.as_str());
+            }
+        }
+        Auth::AwsSigV4 { .. } => {
+            for name in ["x-amz-date", "x-amz-security-token", "x-amz-content-sha256"] {
+                headers.remove(name);
+            }
+            headers.remove(AUTHORIZATION);
+        }
+        _ => return Ok(()),
+    }
+    if same_host {
+        sign_request(request, auth, body, now)?;
+    }
+    Ok(())
+}
+
+/// Canonical form signed by `Auth::Hmac`, one item per line: method, path,
+/// sorted query string, each signed header as `name:value`, the hex SHA-256
+/// of the body and the timestamp.
+fn hmac_string_to_sign(
+    request: &Request<HyperBody>,
+    signed_headers: &[String],
+    body: &[u8],
+    timestamp: &str,
+) -> String {
+    let mut lines = vec![
+        request.method().as_str().to_string(),
+        request.uri().path().to_string(),
+        canonical_query(request.uri().query()),
+    ];
+    for name in signed_headers {
+        let name = name.to_ascii_lowercase();
+        let value = request
+            .headers()
+            .get(name.as_str())
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .trim();
+        lines.push(format!("{}:{}", name, value));
+    }
+    lines.push(hex::encode(Sha256::digest(body)));
+    lines.push(timestamp.to_string());
+    lines.join("\n")
+}
+
+struct AwsCredentials<'a> {
+    access_key: &'a str,
+    secret_key: &'a str,
+    session_token: Option<&'a str>,
+    region: &'a str,
+    service: &'a str,
+}
+
+fn sign_aws_v4(
+    request: &mut Request<HyperBody>,
+    credentials: &AwsCredentials<'_>,
+    body: &[u8],
+    now: DateTime<Utc>,
+) -> Result<(), RequestError> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let payload_hash = hex::encode(Sha256::digest(body));
+    let is_s3 = credentials.service == "s3";
+
+    if !request.headers().contains_key(HOST)
+        && let Some(authority) = request.uri().authority()
+    {
+        let host = authority.as_str().to_string();
+        insert_header(request, HOST.as_str(), &host)?;
+    }
+    insert_header(request, "x-amz-date", &amz_date)?;
+    if let Some(token) = credentials.session_token {
+        insert_header(request, "x-amz-security-token", token)?;
+    }
+    if is_s3 {
+        insert_header(request, "x-amz-content-sha256", &payload_hash)?;
+    }
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for name in request.headers().keys() {
+        let name = name.as_str();
+        if name == "host" || name == "content-type" || name.starts_with("x-amz-") {
+            let values: Vec<String> = request
+                .headers()
+                .get_all(name)
+                .iter()
+                .map(|v| collapse_spaces(&String::from_utf8_lossy(v.as_bytes())))
+                .collect();
+            headers.push((name.to_string(), values.join(",")));
+        }
+    }
+    headers.sort();
+
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = [
+        request.method().as_str(),
+        &canonical_uri(request.uri().path(), !is_s3),
+        &canonical_query(request.uri().query()),
+        &canonical_headers,
+        &signed_headers,
+        &payload_hash,
+    ]
+    .join("\n");
+
+    let scope = format!("{}/{}/{}/aws4_request", date, credentials.region, credentials.service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let k_date = hmac_sha256(format!("AWS4{}", credentials.secret_key).as_bytes(), date.as_bytes());
+    let k_region = hmac_sha256(&k_date, credentials.region.as_bytes());
+    let k_service = hmac_sha256(&k_region, credentials.service.as_bytes());
+    let k_signing = hmac_sha256(&k_service, b"aws4_request");
+    let signature = hex::encode(hmac_sha256(&k_signing, string_to_sign.as_bytes()));
+
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key, scope, signed_headers, signature
+    );
+    insert_header(request, AUTHORIZATION.as_str(), &authorization)
+}
+
+fn canonical_uri(path: &str, double_encode: bool) -> String {
+    if path.is_empty() {
+        return "/".to_string();
+    }
+    if !double_encode {
+        return path.to_string();
+    }
+    path.split('/')
+        .map(aws_uri_encode)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn canonical_query(query: Option<&str>) -> String {
+    let Some(query) = query else {
+        return String::new();
+    };
+    let mut pairs: Vec<(String, String)> = form_urlencoded::parse(query.as_bytes())
+        .map(|(k, v)| (aws_uri_encode(&k), aws_uri_encode(&v)))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// RFC 3986 encoding as required by SigV4: everything except unreserved characters.
+fn aws_uri_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+fn collapse_spaces(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn insert_header(request: &mut Request<HyperBody>, name: &str, value: &str) -> Result<(), RequestError> {
+    let name = HeaderName::from_bytes(name.as_bytes())
+        .map_err(|e| RequestError::new(RequestErrorKind::InvalidHeader, format!("{}: {}", name, e)))?;
+    let value = HeaderValue::from_str(value)
+        .map_err(|e| RequestError::new(RequestErrorKind::InvalidHeader, format!("{}: {}", name, e)))?;
+    request.headers_mut().insert(name, value);
+    Ok(())
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Credentials, region, service and date of the AWS SigV4 test suite.
+    fn aws_suite() -> (Auth, DateTime<Utc>) {
+        let auth = Auth::AwsSigV4 {
+            access_key: "AKIDEXAMPLE".to_string(),
+            secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+            region: "us-east-1".to_string(),
+            service: "service".to_string(),
+        };
+        (auth, Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap())
+    }
+
+    fn aws_authorization(method: &str, uri: &str, content_type: Option<&str>, body: &[u8]) -> String {
+        let (auth, now) = aws_suite();
+        let mut builder = Request::builder().method(method).uri(uri);
+        if let Some(content_type) = content_type {
+            builder = builder.header("content-type", content_type);
+        }
+        let mut request = builder.body(HyperBody::empty()).unwrap();
+        sign_request(&mut request, &auth, body, now).unwrap();
+        assert_eq!(request.headers()["x-amz-date"], "20150830T123600Z");
+        assert_eq!(request.headers()[HOST], "example.amazonaws.com");
+        request.headers()[AUTHORIZATION].to_str().unwrap().to_string()
+    }
+
+    fn suite_signature(signed_headers: &str, signature: &str) -> String {
+        format!(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders={}, Signature={}",
+            signed_headers, signature
+        )
+    }
+
+    #[test]
+    fn aws_get_vanilla() {
+        assert_eq!(
+            aws_authorization("GET", "https://example.amazonaws.com/", None, b""),
+            suite_signature("host;x-amz-date", "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"),
+        );
+    }
+
+    #[test]
+    fn aws_get_vanilla_query_order_key_case() {
+        assert_eq!(
+            aws_authorization("GET", "https://example.amazonaws.com/?Param2=value2&Param1=value1", None, b""),
+            suite_signature("host;x-amz-date", "b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500"),
+        );
+    }
+
+    #[test]
+    fn aws_get_vanilla_query_order_value() {
+        assert_eq!(
+            aws_authorization("GET", "https://example.amazonaws.com/?Param1=value2&Param1=value1", None, b""),
+            suite_signature("host;x-amz-date", "5772eed61e12b33fae39ee5e7012498b51d56abc0abb7c60486157bd471c4694"),
+        );
+    }
+
+    #[test]
+    fn aws_post_x_www_form_urlencoded() {
+        assert_eq!(
+            aws_authorization(
+                "POST",
+                "https://example.amazonaws.com/",
+                Some("application/x-www-form-urlencoded"),
+                b"Param1=value1",
+            ),
+            suite_signature(
+                "content-type;host;x-amz-date",
+                "ff11897932ad3f4e8b18135d722051e5ac45fc38421b1da7b9d196a0fe09473a",
+            ),
+        );
+    }
+
+    fn hmac_auth(algorithm: HmacAlgorithm) -> Auth {
+        Auth::Hmac {
+            secret: "secret".to_string(),
+            key_id: Some("client-1".to_string()),
+            algorithm,
+            signed_headers: vec!["Content-Type".to_string()],
+            signature_header: "X-Signature".to_string(),
+            timestamp_header: "X-Timestamp".to_string(),
+            key_id_header: "X-Key-Id".to_string(),
+        }
+    }
+
+    fn hmac_signed(algorithm: HmacAlgorithm) -> Request<HyperBody> {
+        let mut request = Request::builder()
+            .method("POST")
+            .uri("https://api.example.com/orders?b=2&a=1")
+            .header("content-type", "application/json")
+            .body(HyperBody::empty())
+            .unwrap();
+        let now = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        sign_request(&mut request, &hmac_auth(algorithm), br#"{"id":1}"#, now).unwrap();
+        request
+    }
+
+    #[test]
+    fn hmac_string_to_sign_is_canonical() {
+        let request = hmac_signed(HmacAlgorithm::Sha256);
+        assert_eq!(
+            hmac_string_to_sign(&request, &["Content-Type".to_string()], br#"{"id":1}"#, "1700000000"),
+            "POST\n/orders\na=1&b=2\ncontent-type:application/json\n\
+             037c9214eef74cc3887f3a4f085b4e17d76280dafd273b0ee160c09c4ba1cfd4\n1700000000",
+        );
+        assert_eq!(request.headers()["x-timestamp"], "1700000000");
+        assert_eq!(request.headers()["x-key-id"], "client-1");
+    }
+
+    #[test]
+    fn hmac_sha256_signature() {
+        assert_eq!(
+            hmac_signed(HmacAlgorithm::Sha256).headers()["x-signature"],
+            "8b75ab93bec09af6f7e5ecafec0854982bf934902bb3310a91730c12e09e1f95",
+        );
+    }
+
+    #[test]
+    fn hmac_sha512_signature() {
+        assert_eq!(
+            hmac_signed(HmacAlgorithm::Sha512).headers()["x-signature"],
+            "6710a3c9b947993188d4f2c4b512ba3d089194877a88e619480feaf85bfc01e1\
+             e4706cbae65474e4e0b5e0474ade248863d06d839203ff8b0ade011443e1e621",
+        );
+    }
+
+    #[test]
+    fn redirect_hop_gets_its_own_signature() {
+        let (auth, now) = aws_suite();
+        let mut request = Request::builder()
+            .method("POST")
+            .uri("https://example.amazonaws.com/old")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(HyperBody::empty())
+            .unwrap();
+        sign_request(&mut request, &auth, b"Param1=value1", now).unwrap();
+
+        // What a 303 to `/?Param2=value2&Param1=value1` turns the request into.
+        *request.uri_mut() = "https://example.amazonaws.com/?Param2=value2&Param1=value1".parse().unwrap();
+        *request.method_mut() = hyper::Method::GET;
+        request.headers_mut().remove("content-type");
+        request.headers_mut().remove(HOST);
+        resign_redirect(&mut request, &auth, b"", true, now).unwrap();
+
+        assert_eq!(
+            request.headers()[AUTHORIZATION],
+            suite_signature("host;x-amz-date", "b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500").as_str(),
+        );
+    }
+
+    #[test]
+    fn redirect_to_another_host_drops_the_signature() {
+        let mut request = hmac_signed(HmacAlgorithm::Sha256);
+        *request.uri_mut() = "https://other.example.com/orders".parse().unwrap();
+        let now = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        resign_redirect(&mut request, &hmac_auth(HmacAlgorithm::Sha256), br#"{"id":1}"#, false, now).unwrap();
+
+        for name in ["x-signature", "x-timestamp", "x-key-id"] {
+            assert!(!request.headers().contains_key(name), "{} was kept", name);
+        }
+    }
+}
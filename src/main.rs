@@ -4,6 +4,8 @@ mod models;
 mod ws; 
 mod utils;
 mod client;
+mod runner;
+mod template;
 
 
 #[actix_web::main]
@@ -1,5 +1,8 @@
+use hyper::body::Bytes;
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct DslConfig {
@@ -8,7 +11,28 @@ pub struct DslConfig {
     pub target: String,
     pub method: HttpMethod,
     pub concurrency: u64,
-    pub duration: u64,
+
+    /// Test length in seconds. With `iterations` or `iterations_per_vu` it is
+    /// an optional cap instead.
+    #[serde(default)]
+    pub duration: Option<u64>,
+
+    /// Iterations to run across all virtual users before the test ends.
+    #[serde(default)]
+    pub iterations: Option<u64>,
+
+    /// Iterations each virtual user runs before it stops.
+    #[serde(default)]
+    pub iterations_per_vu: Option<u64>,
+
+    /// Seconds at the start of the test whose results are reported apart from
+    /// the measured ones, so warm-up effects don't skew them.
+    #[serde(default)]
+    pub warmup_secs: Option<u64>,
+
+    /// Length in seconds of each interval of the report's time series.
+    #[serde(default = "default_timeseries_bucket_secs")]
+    pub timeseries_bucket_secs: u64,
 
     #[serde(default)]
     pub body: Option<Body>,
@@ -21,10 +45,131 @@ pub struct DslConfig {
 
     #[serde(default)]
     pub headers: Option<HashMap<String, String>>,
+
+    #[serde(default)]
+    pub connection: Option<ConnectionConfig>,
+
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+
+    #[serde(default)]
+    pub response_body: ResponseBodyMode,
+
+    #[serde(default)]
+    pub compression: Option<CompressionConfig>,
+
+    /// Keeps a cookie store per virtual user, fed by `Set-Cookie` responses.
+    #[serde(default)]
+    pub cookie_jar: bool,
+
+    #[serde(default)]
+    pub redirects: Option<RedirectConfig>,
+
+    /// Rows substituted into `{{column}}` placeholders.
+    #[serde(default)]
+    pub data: Option<DataConfig>,
+
+    /// Weighted requests, one picked at random per iteration instead of the single request.
+    #[serde(default)]
+    pub requests: Vec<WeightedRequest>,
+
+    /// Name the test's request is reported under instead of its URL, e.g.
+    /// `/users/{id}` for a target built from IDs.
+    #[serde(default)]
+    pub url_group: Option<String>,
+
+    /// Labels attached to the test's requests, e.g. `endpoint: checkout`.
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+
+    /// Tag combinations the report aggregates by, e.g. `[["endpoint"], ["endpoint", "tier"]]`.
+    /// Empty means one breakdown per tag name.
+    #[serde(default)]
+    pub tag_groups: Vec<Vec<String>>,
+
+    /// Rhai script run once per iteration instead of the single request.
+    #[serde(default)]
+    pub script: Option<ScriptConfig>,
+
+    /// Requests run once before the load starts.
+    #[serde(default)]
+    pub setup: Vec<PhaseRequest>,
+
+    /// Requests run once after the load ends, including when it's aborted.
+    #[serde(default)]
+    pub teardown: Vec<PhaseRequest>,
+
+    /// Groups of virtual users with their own load shape, run side by side
+    /// instead of the `concurrency` users.
+    #[serde(default)]
+    pub scenarios: Vec<Scenario>,
+
+    /// Pause after each iteration, and on `think()` inside a script.
+    #[serde(default)]
+    pub think_time: Option<ThinkTime>,
+
+    /// Minimum interval between the starts of two iterations of a virtual user.
+    #[serde(default)]
+    pub pacing_ms: Option<u64>,
+}
+
+/// A request replacing the test's own, as used by the request mix and the
+/// setup and teardown phases. Headers and query parameters are added to the
+/// test-level ones; the body belongs to the request alone.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RequestSpec {
+    pub target: String,
+    pub method: HttpMethod,
+
+    #[serde(default)]
+    pub body: Option<Body>,
+
+    #[serde(default)]
+    pub query_params: Option<HashMap<String, String>>,
+
+    #[serde(default)]
+    pub headers: Option<HashMap<String, String>>,
+
+    /// Added to the tags inherited from the test.
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+
+    /// URL group the request is reported under; defaults to its method and target.
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// One entry of a request mix.
+#[derive(Debug, Deserialize, Clone)]
+pub struct WeightedRequest {
+    pub weight: u32,
+
+    #[serde(flatten)]
+    pub request: RequestSpec,
+}
+
+/// A request run once before or after the load phase.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PhaseRequest {
+    #[serde(flatten)]
+    pub request: RequestSpec,
+
+    /// Values taken from the response, available afterwards as `{{name}}`.
+    #[serde(default)]
+    pub extract: HashMap<String, Extraction>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "from")]
+pub enum Extraction {
+    /// A JSON Pointer into the response body, e.g. `/data/id`.
+    Json { pointer: String },
+    Header { name: String },
 }
 
 #[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "UPPERCASE")]
+#[allow(clippy::upper_case_acronyms)]
 pub enum HttpMethod {
     GET,
     POST,
@@ -40,6 +185,56 @@ pub enum HttpMethod {
 pub enum Body {
     Json(serde_json::Value),
     Xml(String),
+    Form(HashMap<String, String>),
+    Multipart(Vec<MultipartPart>),
+    Raw { content_type: String, data: String },
+    Binary {
+        #[serde(default)]
+        content_type: Option<String>,
+        #[serde(flatten)]
+        source: BinarySource,
+    },
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MultipartPart {
+    pub name: String,
+
+    /// Text value; ignored when `file` is set.
+    #[serde(default)]
+    pub value: Option<String>,
+
+    #[serde(default)]
+    pub file: Option<BinarySource>,
+
+    #[serde(default)]
+    pub filename: Option<String>,
+
+    #[serde(default)]
+    pub content_type: Option<String>,
+}
+
+/// Binary content read from a worker-local file or given inline as base64.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct BinarySource {
+    #[serde(default)]
+    pub path: Option<String>,
+
+    #[serde(default)]
+    pub base64: Option<String>,
+
+    /// Decoded content, filled once before the test starts.
+    #[serde(skip)]
+    pub loaded: Option<Payload>,
+}
+
+#[derive(Clone)]
+pub struct Payload(pub Bytes);
+
+impl fmt::Debug for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Payload({} bytes)", self.0.len())
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -49,4 +244,383 @@ pub enum Auth {
     Basic { username: String, password: String },
     Bearer { token: String },
     ApiKey { key_name: String, key_value: String, add_to: String },
+    OAuth2 {
+        token_url: String,
+        #[serde(default)]
+        grant_type: OAuthGrant,
+        client_id: String,
+        #[serde(default)]
+        client_secret: Option<String>,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+        #[serde(default)]
+        scope: Option<String>,
+    },
+    Hmac {
+        secret: String,
+        #[serde(default)]
+        key_id: Option<String>,
+        #[serde(default)]
+        algorithm: HmacAlgorithm,
+        #[serde(default)]
+        signed_headers: Vec<String>,
+        #[serde(default = "default_signature_header")]
+        signature_header: String,
+        #[serde(default = "default_timestamp_header")]
+        timestamp_header: String,
+        #[serde(default = "default_key_id_header")]
+        key_id_header: String,
+    },
+    Digest { username: String, password: String },
+    Jwt {
+        #[serde(default)]
+        algorithm: JwtAlgorithm,
+        /// Shared secret for `HS256`.
+        #[serde(default)]
+        secret: Option<String>,
+        /// PEM private key for `RS256` and `ES256`.
+        #[serde(default)]
+        private_key_pem: Option<String>,
+        #[serde(default)]
+        key_id: Option<String>,
+        /// String values may contain placeholders such as `{{vu_id}}`.
+        #[serde(default)]
+        claims: serde_json::Map<String, serde_json::Value>,
+        #[serde(default = "default_jwt_expires_in")]
+        expires_in_secs: u64,
+        #[serde(default)]
+        issue_per: JwtIssuePolicy,
+    },
+    AwsSigV4 {
+        access_key: String,
+        secret_key: String,
+        #[serde(default)]
+        session_token: Option<String>,
+        region: String,
+        service: String,
+    },
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HmacAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JwtAlgorithm {
+    #[default]
+    HS256,
+    RS256,
+    ES256,
+}
+
+/// Whether a virtual user reuses its JWT until it expires or signs a new one
+/// for every request.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JwtIssuePolicy {
+    #[default]
+    User,
+    Request,
+}
+
+fn default_timeseries_bucket_secs() -> u64 {
+    10
+}
+
+fn default_jwt_expires_in() -> u64 {
+    300
+}
+
+fn default_signature_header() -> String {
+    "X-Signature".to_string()
+}
+
+fn default_timestamp_header() -> String {
+    "X-Timestamp".to_string()
+}
+
+fn default_key_id_header() -> String {
+    "X-Key-Id".to_string()
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OAuthGrant {
+    #[default]
+    ClientCredentials,
+    Password,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ConnectionConfig {
+    #[serde(default)]
+    pub policy: ConnectionPolicy,
+
+    #[serde(default)]
+    pub pool_max_idle_per_host: Option<usize>,
+
+    #[serde(default)]
+    pub pool_idle_timeout_secs: Option<u64>,
+}
+
+/// How virtual users share TCP connections to the target.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConnectionPolicy {
+    /// All virtual users draw from a single keep-alive pool.
+    #[default]
+    SharedPool,
+    /// Each virtual user owns its own pool.
+    PerUser,
+    /// Every request opens a fresh connection.
+    NoKeepAlive,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct TlsConfig {
+    /// Extra trusted CA certificates (PEM, may contain several).
+    #[serde(default)]
+    pub ca_cert_pem: Option<String>,
+
+    #[serde(default)]
+    pub client_cert_pem: Option<String>,
+
+    /// PKCS#8 private key matching `client_cert_pem`.
+    #[serde(default)]
+    pub client_key_pem: Option<String>,
+
+    /// Disables certificate and hostname verification. Never use against production.
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+
+    /// Name sent in SNI and checked against the server certificate instead of the URL host.
+    #[serde(default)]
+    pub server_name: Option<String>,
+}
+
+/// What to do with response bodies once they are read.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResponseBodyMode {
+    /// Stream the body to completion and drop each chunk.
+    #[default]
+    Discard,
+    /// Hold the whole body in memory until it has been fully received.
+    Buffer,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct CompressionConfig {
+    /// Compresses request bodies and sets `Content-Encoding`.
+    #[serde(default)]
+    pub request: Option<Encoding>,
+
+    /// Sent as `Accept-Encoding`; matching responses are decompressed.
+    #[serde(default)]
+    pub accept: Vec<Encoding>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+    Br,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RedirectConfig {
+    #[serde(default)]
+    pub policy: RedirectPolicy,
+
+    #[serde(default = "default_max_redirects")]
+    pub max_redirects: u32,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RedirectPolicy {
+    /// Record the 3xx response as the result.
+    #[default]
+    None,
+    /// Follow redirects to any host.
+    Follow,
+    /// Follow redirects only while they stay on the original host.
+    SameHost,
+}
+
+fn default_max_redirects() -> u32 {
+    10
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DataConfig {
+    #[serde(default)]
+    pub format: DataFormat,
+
+    /// Dataset sent with the test: CSV or JSON text, or a JSON array of objects.
+    #[serde(default)]
+    pub content: Option<serde_json::Value>,
+
+    /// Dataset file on the worker.
+    #[serde(default)]
+    pub path: Option<String>,
+
+    #[serde(default)]
+    pub selection: RowSelection,
+
+    #[serde(default)]
+    pub on_exhausted: DataExhaustion,
+
+    /// Parsed rows, filled once before the test starts.
+    #[serde(skip)]
+    pub rows: Option<Dataset>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DataFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RowSelection {
+    /// Rows are handed out in order across all virtual users.
+    #[default]
+    Sequential,
+    Random,
+    /// Each row belongs to a single virtual user.
+    UniquePerVu,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DataExhaustion {
+    /// Start over from the first row.
+    #[default]
+    Recycle,
+    /// Stop the virtual users that ran out of rows.
+    Stop,
+}
+
+pub type DataRow = Arc<HashMap<String, String>>;
+
+#[derive(Clone)]
+pub struct Dataset(pub Arc<Vec<DataRow>>);
+
+impl fmt::Debug for Dataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Dataset({} rows)", self.0.len())
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScriptConfig {
+    pub source: String,
+
+    /// Rhai operations allowed per iteration.
+    #[serde(default = "default_script_max_operations")]
+    pub max_operations: u64,
+
+    /// Requests allowed per iteration.
+    #[serde(default = "default_script_max_requests")]
+    pub max_requests: u32,
+
+    /// Compiled script, filled once before the test starts.
+    #[serde(skip)]
+    pub ast: Option<Arc<rhai::AST>>,
+}
+
+fn default_script_max_operations() -> u64 {
+    1_000_000
+}
+
+fn default_script_max_requests() -> u32 {
+    100
+}
+
+/// Distribution of the pause a virtual user takes between steps.
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(tag = "type")]
+pub enum ThinkTime {
+    Fixed { ms: u64 },
+    /// Any value in `min_ms..=max_ms`, equally likely.
+    Uniform { min_ms: u64, max_ms: u64 },
+    /// Gaussian around `mean_ms`; negative draws become 0.
+    Normal { mean_ms: u64, std_dev_ms: u64 },
+    /// Memoryless pauses averaging `mean_ms`, like independent users arriving.
+    Exponential { mean_ms: u64 },
+}
+
+/// A named group of virtual users. Request fields left out are taken from the test.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Scenario {
+    pub name: String,
+    pub executor: Executor,
+
+    #[serde(default)]
+    pub start_offset_secs: u64,
+
+    /// Defaults to the rest of the test.
+    #[serde(default)]
+    pub duration: Option<u64>,
+
+    #[serde(default)]
+    pub target: Option<String>,
+
+    #[serde(default)]
+    pub method: Option<HttpMethod>,
+
+    #[serde(default)]
+    pub body: Option<Body>,
+
+    #[serde(default)]
+    pub query_params: Option<HashMap<String, String>>,
+
+    #[serde(default)]
+    pub headers: Option<HashMap<String, String>>,
+
+    /// URL group for the scenario's own `target`.
+    #[serde(default)]
+    pub url_group: Option<String>,
+
+    #[serde(default)]
+    pub requests: Vec<WeightedRequest>,
+
+    /// Added to the test's tags for every request of the scenario.
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+
+    #[serde(default)]
+    pub script: Option<ScriptConfig>,
+
+    #[serde(default)]
+    pub think_time: Option<ThinkTime>,
+
+    #[serde(default)]
+    pub pacing_ms: Option<u64>,
+}
+
+/// How a scenario drives its virtual users.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum Executor {
+    /// A fixed number of users looping for the whole scenario.
+    ConstantVus { vus: u64 },
+    /// Users added or removed linearly through each stage; the last target
+    /// holds until the scenario ends.
+    RampingVus {
+        #[serde(default)]
+        start_vus: u64,
+        stages: Vec<RampStage>,
+    },
+    /// Iterations started at a fixed rate regardless of response times, on at
+    /// most `max_vus` users.
+    ConstantArrivalRate { rate_per_sec: f64, max_vus: u64 },
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RampStage {
+    pub duration_secs: u64,
+    pub target: u64,
 }
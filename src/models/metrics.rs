@@ -1,4 +1,8 @@
 use std::collections::HashMap;
+use crate::client::error::RequestError;
+use crate::utils::stats::Percentiles;
+
+const MAX_ERROR_SAMPLES: usize = 5;
 
 #[derive(Debug, Default)]
 pub struct Metrics {
@@ -21,4 +25,46 @@ pub struct Metrics {
     pub timestamp: String,
 
     pub status_counts: HashMap<String, u64>,
+
+    pub connections_opened: u64,
+    pub redirects: u64,
+    pub script_errors: u64,
+    pub iterations: u64,
+    pub dropped_iterations: u64,
+
+    pub error_counts: HashMap<String, u64>,
+    pub error_samples: HashMap<String, Vec<String>>,
+
+    pub median_ttfb: f64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub request_body_bytes: u64,
+    pub bytes_decoded: u64,
+    pub sent_throughput: f64,
+    pub received_throughput: f64,
+    pub response_size: Percentiles,
+
+    pub auth_requests: u64,
+    pub auth_failures: u64,
+    pub auth_average_duration: f64,
+}
+
+/// Raw per-request samples kept for percentile calculations.
+#[derive(Debug, Default)]
+pub struct Samples {
+    pub response_times: Vec<f64>,
+    pub ttfb_times: Vec<f64>,
+    pub response_sizes: Vec<f64>,
+}
+
+impl Metrics {
+    pub fn record_error(&mut self, err: &RequestError) {
+        let kind = err.kind.as_str();
+        *self.error_counts.entry(kind.to_string()).or_insert(0) += 1;
+
+        let samples = self.error_samples.entry(kind.to_string()).or_default();
+        if samples.len() < MAX_ERROR_SAMPLES && !samples.contains(&err.message) {
+            samples.push(err.message.clone());
+        }
+    }
 }
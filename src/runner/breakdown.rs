@@ -0,0 +1,45 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::client::error::RequestError;
+use crate::client::ResponseInfo;
+
+use super::{tally_summary, Tally};
+
+/// Measured results split by a key such as a tag combination or URL group,
+/// one tally per key seen.
+pub struct Breakdown {
+    tallies: Mutex<BTreeMap<String, Arc<Tally>>>,
+}
+
+impl Breakdown {
+    pub fn new() -> Self {
+        Self {
+            tallies: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn add(&self, key: String, result: &Result<ResponseInfo, RequestError>) {
+        let tally = Arc::clone(
+            self.tallies
+                .lock()
+                .unwrap()
+                .entry(key)
+                .or_insert_with(|| Arc::new(Tally::new())),
+        );
+        tally.add(result);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tallies.lock().unwrap().is_empty()
+    }
+
+    pub fn report(&self, elapsed_secs: f64) -> serde_json::Map<String, serde_json::Value> {
+        self.tallies
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, tally)| (key.clone(), tally_summary(tally, elapsed_secs)))
+            .collect()
+    }
+}
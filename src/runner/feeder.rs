@@ -0,0 +1,130 @@
+use crate::models::dsl_model::{DataConfig, DataExhaustion, DataFormat, DataRow, Dataset, RowSelection};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Reads and parses the dataset once, so rows are ready before the test starts.
+pub fn load_dataset(data: &mut DataConfig, concurrency: u64) -> Result<(), String> {
+    if data.rows.is_none() {
+        let text = match (&data.content, &data.path) {
+            (Some(Value::String(text)), None) => text.clone(),
+            (Some(content @ Value::Array(_)), None) => content.to_string(),
+            (None, Some(path)) => std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?,
+            (Some(_), None) => return Err("`content` must be a string or a JSON array".to_string()),
+            _ => return Err("Data needs exactly one of `content` or `path`".to_string()),
+        };
+        let format = match &data.content {
+            Some(Value::Array(_)) => DataFormat::Json,
+            _ => data.format,
+        };
+        let rows = match format {
+            DataFormat::Csv => parse_csv(&text)?,
+            DataFormat::Json => parse_json(&text)?,
+        };
+        data.rows = Some(Dataset(Arc::new(rows)));
+    }
+
+    let rows = data.rows.as_ref().map(|d| d.0.len()).unwrap_or(0);
+    if rows == 0 {
+        return Err("Dataset has no rows".to_string());
+    }
+    if data.selection == RowSelection::UniquePerVu && (rows as u64) < concurrency {
+        return Err(format!(
+            "UniquePerVu needs at least one row per virtual user ({} rows for concurrency {})",
+            rows, concurrency
+        ));
+    }
+    Ok(())
+}
+
+fn parse_csv(text: &str) -> Result<Vec<DataRow>, String> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(text.as_bytes());
+    let headers = reader.headers().map_err(|e| format!("Invalid CSV: {}", e))?.clone();
+
+    reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| format!("Invalid CSV: {}", e))?;
+            let row = headers
+                .iter()
+                .zip(record.iter())
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<HashMap<_, _>>();
+            Ok(Arc::new(row))
+        })
+        .collect()
+}
+
+fn parse_json(text: &str) -> Result<Vec<DataRow>, String> {
+    let items: Vec<serde_json::Map<String, Value>> =
+        serde_json::from_str(text).map_err(|e| format!("Invalid JSON dataset, expected an array of objects: {}", e))?;
+
+    Ok(items
+        .into_iter()
+        .map(|item| {
+            let row = item
+                .into_iter()
+                .map(|(name, value)| {
+                    let value = match value {
+                        Value::String(s) => s,
+                        other => other.to_string(),
+                    };
+                    (name, value)
+                })
+                .collect::<HashMap<_, _>>();
+            Arc::new(row)
+        })
+        .collect())
+}
+
+/// Hands out dataset rows to virtual users according to the selection mode.
+pub struct Feeder {
+    rows: Arc<Vec<DataRow>>,
+    selection: RowSelection,
+    on_exhausted: DataExhaustion,
+    concurrency: usize,
+    cursor: AtomicUsize,
+}
+
+impl Feeder {
+    pub fn new(data: &DataConfig, concurrency: u64) -> Option<Self> {
+        Some(Self {
+            rows: Arc::clone(&data.rows.as_ref()?.0),
+            selection: data.selection,
+            on_exhausted: data.on_exhausted,
+            concurrency: concurrency.max(1) as usize,
+            cursor: AtomicUsize::new(0),
+        })
+    }
+
+    /// Row for a virtual user's `iteration`, or `None` once it ran out of
+    /// rows under `DataExhaustion::Stop`.
+    pub fn next_row(&self, vu_index: usize, iteration: u64) -> Option<DataRow> {
+        let len = self.rows.len();
+        let index = match self.selection {
+            RowSelection::Sequential => {
+                let next = self.cursor.fetch_add(1, Ordering::Relaxed);
+                self.wrap(next, len)?
+            }
+            RowSelection::Random => rand::random_range(0..len),
+            RowSelection::UniquePerVu => {
+                // Virtual user `i` owns rows i, i + N, i + 2N, ...
+                let owned = (len - vu_index).div_ceil(self.concurrency);
+                vu_index + self.wrap(iteration as usize, owned)? * self.concurrency
+            }
+        };
+        self.rows.get(index).cloned()
+    }
+
+    fn wrap(&self, position: usize, len: usize) -> Option<usize> {
+        if position < len {
+            Some(position)
+        } else {
+            match self.on_exhausted {
+                DataExhaustion::Recycle => Some(position % len),
+                DataExhaustion::Stop => None,
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use crate::models::dsl_model::{DslConfig, RequestSpec};
+
+/// The test's weighted requests, each expanded into a full config.
+pub struct RequestMix {
+    /// Cumulative weight up to and including each entry.
+    bounds: Vec<u64>,
+    configs: Vec<Arc<DslConfig>>,
+}
+
+impl RequestMix {
+    /// `None` when the test has no mix.
+    pub fn new(config: &DslConfig) -> Result<Option<Self>, String> {
+        if config.requests.is_empty() {
+            return Ok(None);
+        }
+
+        let mut bounds = Vec::with_capacity(config.requests.len());
+        let mut configs = Vec::with_capacity(config.requests.len());
+        let mut total = 0u64;
+        for entry in &config.requests {
+            total += entry.weight as u64;
+            bounds.push(total);
+
+            configs.push(Arc::new(with_request(config, &entry.request)));
+        }
+
+        if total == 0 {
+            return Err("at least one request needs a weight above 0".to_string());
+        }
+        Ok(Some(Self { bounds, configs }))
+    }
+
+    pub fn configs(&self) -> impl Iterator<Item = &DslConfig> {
+        self.configs.iter().map(|c| c.as_ref())
+    }
+
+    /// Picks an entry with probability proportional to its weight.
+    pub fn pick(&self) -> &Arc<DslConfig> {
+        let total = self.bounds.last().copied().unwrap_or_default();
+        let roll = rand::random_range(0..total);
+        let index = self.bounds.partition_point(|&bound| bound <= roll);
+        &self.configs[index]
+    }
+}
+
+/// The test config sending `request` instead of its own.
+pub fn with_request(base: &DslConfig, request: &RequestSpec) -> DslConfig {
+    let mut config = base.clone();
+    config.requests = Vec::new();
+    config.target = request.target.clone();
+    config.method = request.method.clone();
+    config.body = request.body.clone();
+    if let Some(params) = &request.query_params {
+        config.query_params.get_or_insert_with(Default::default).extend(params.clone());
+    }
+    if let Some(headers) = &request.headers {
+        config.headers.get_or_insert_with(Default::default).extend(headers.clone());
+    }
+    config.tags.extend(request.tags.clone());
+    config.url_group = request.name.clone();
+    config
+}
@@ -0,0 +1,620 @@
+pub mod breakdown;
+pub mod feeder;
+pub mod mix;
+pub mod pacing;
+pub mod phase;
+pub mod scenario;
+pub mod script;
+pub mod tags;
+pub mod timeseries;
+mod vu;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use cookie_store::CookieStore;
+use tokio::sync::mpsc;
+use tokio::task;
+use tokio::time::sleep;
+
+use crate::client::connector::TlsConnector;
+use crate::client::error::RequestError;
+use crate::client::jwt::{JwtSession, JwtSigner};
+use crate::client::oauth::OAuthTokenCache;
+use crate::client::{build_client, HttpsClient, ResponseInfo, Session};
+use crate::models::dsl_model::{Auth, ConnectionPolicy, DslConfig, Executor};
+use crate::models::metrics::{Metrics, Samples};
+use crate::utils::stats::{calculate_median, calculate_percentiles};
+use crate::ws::ACTIVE_CONNECTIONS;
+
+use breakdown::Breakdown;
+use feeder::Feeder;
+use mix::RequestMix;
+use phase::Phase;
+use scenario::{ScenarioPlan, ScenarioRun};
+use tags::TagTallies;
+use timeseries::TimeSeries;
+
+/// Whether a virtual user should keep going.
+pub type KeepRunning = Arc<dyn Fn() -> bool + Send + Sync>;
+
+/// Counts one request's outcome and streams it to the client.
+pub type RecordResult = Arc<dyn Fn(&DslConfig, Result<ResponseInfo, RequestError>) + Send + Sync>;
+
+/// Flags shared between the WebSocket session and a running test.
+pub struct TestControl {
+    pub cancel_flag: Arc<AtomicBool>,
+    pub running_test: Arc<AtomicBool>,
+}
+
+/// Test-wide state every scenario and virtual user draws from.
+pub struct Shared {
+    tls: TlsConnector,
+    /// `None` when each virtual user gets its own pool.
+    client: Option<Arc<HttpsClient>>,
+    connections_opened: Arc<AtomicU64>,
+    oauth: Option<Arc<OAuthTokenCache>>,
+    jwt_signer: Option<Arc<JwtSigner>>,
+    feeder: Option<Arc<Feeder>>,
+    /// Values extracted by the setup phase.
+    vars: Option<Arc<HashMap<String, String>>>,
+    request_counter: Arc<AtomicU64>,
+    total: Arc<Tally>,
+    /// Results from before `measure_from`, kept apart from `total`.
+    warmup: Arc<Tally>,
+    measure_from: Instant,
+    /// Every result of the run, warm-up included, by interval.
+    series: TimeSeries,
+    /// Measured results by tag.
+    tags: TagTallies,
+    /// Measured results by URL group.
+    url_groups: Breakdown,
+    tx: mpsc::UnboundedSender<String>,
+    request_id: String,
+    started: Instant,
+    /// `None` when the test only stops on its iteration counts.
+    end_time: Option<Instant>,
+    iteration_limit: Option<u64>,
+    iterations_started: AtomicU64,
+    running: AtomicBool,
+    cancel_flag: Arc<AtomicBool>,
+    active_vus: AtomicU64,
+}
+
+impl Shared {
+    fn keep_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+            && self.end_time.is_none_or(|end| Instant::now() < end)
+            && ACTIVE_CONNECTIONS.load(Ordering::SeqCst) > 0
+            && !self.cancel_flag.load(Ordering::SeqCst)
+    }
+
+    /// Takes one iteration from the test-wide budget; `false` once it's spent.
+    fn claim_iteration(&self) -> bool {
+        match self.iteration_limit {
+            Some(limit) => self.iterations_started.fetch_add(1, Ordering::Relaxed) < limit,
+            None => true,
+        }
+    }
+
+    fn iterations_exhausted(&self) -> bool {
+        self.iteration_limit
+            .is_some_and(|limit| self.iterations_started.load(Ordering::Relaxed) >= limit)
+    }
+
+    fn warming_up(&self) -> bool {
+        Instant::now() < self.measure_from
+    }
+
+    /// Where something happening now is counted: the warm-up tally alone, or
+    /// the whole-run totals plus the scenario's own tally.
+    fn tallies<'a>(&'a self, scenario: Option<&'a Arc<Tally>>) -> impl Iterator<Item = &'a Arc<Tally>> {
+        let warming_up = self.warming_up();
+        let run = if warming_up { &self.warmup } else { &self.total };
+        std::iter::once(run).chain(scenario.filter(|_| !warming_up))
+    }
+}
+
+/// Metrics and samples of one slice of the test: the whole run or a scenario.
+pub struct Tally {
+    pub metrics: Mutex<Metrics>,
+    pub samples: Mutex<Samples>,
+}
+
+impl Tally {
+    fn new() -> Self {
+        Self {
+            metrics: Mutex::new(Metrics {
+                fastest_response: f64::MAX,
+                slowest_response: f64::MIN,
+                status_counts: HashMap::new(),
+                ..Default::default()
+            }),
+            samples: Mutex::new(Samples::default()),
+        }
+    }
+}
+
+pub async fn run_test(
+    config: DslConfig,
+    tls: TlsConnector,
+    jwt_signer: Option<Arc<JwtSigner>>,
+    tx: mpsc::UnboundedSender<String>,
+    control: TestControl,
+) {
+    let TestControl { cancel_flag, running_test } = control;
+
+    let connections_opened = Arc::new(AtomicU64::new(0));
+    let policy = config.connection.as_ref().map(|c| c.policy).unwrap_or_default();
+    let shared_client = if policy == ConnectionPolicy::PerUser {
+        None
+    } else {
+        Some(Arc::new(build_client(&config, tls.clone(), Arc::clone(&connections_opened))))
+    };
+    let config = Arc::new(config);
+
+    let oauth = match &config.auth {
+        Some(auth @ Auth::OAuth2 { .. }) => {
+            let cache = Arc::new(OAuthTokenCache::new());
+            let token_client = match &shared_client {
+                Some(client) => Arc::clone(client),
+                None => Arc::new(build_client(&config, tls.clone(), Arc::clone(&connections_opened))),
+            };
+
+            if let Err(err) = cache.access_token(&token_client, auth).await {
+                let _ = tx.send(serde_json::json!({
+                    "request_id": config.request_id,
+                    "status": "error",
+                    "message": format!("OAuth2 token request failed: {}", err.message),
+                }).to_string());
+                running_test.store(false, Ordering::SeqCst);
+                return;
+            }
+            Some(cache)
+        }
+        _ => None,
+    };
+
+    let feeder = config
+        .data
+        .as_ref()
+        .and_then(|data| Feeder::new(data, scenario::total_vus(&config)))
+        .map(Arc::new);
+
+    // Setup and teardown use a session of their own and stay out of the load metrics.
+    let phase_client = match &shared_client {
+        Some(client) => Arc::clone(client),
+        None => Arc::new(build_client(&config, tls.clone(), Arc::clone(&connections_opened))),
+    };
+    let mut phase_session = Session {
+        oauth: oauth.clone(),
+        jwt: jwt_signer.clone().map(JwtSession::new),
+        cookies: config.cookie_jar.then(CookieStore::default),
+        ..Default::default()
+    };
+    let (setup_results, setup_outcome) =
+        phase::run(Phase::Setup, &config.setup, &config, &phase_client, &mut phase_session, &tx).await;
+    if let Err(err) = setup_outcome {
+        // Teardown still gets a chance to undo whatever setup managed to create.
+        let (teardown_results, _) =
+            phase::run(Phase::Teardown, &config.teardown, &config, &phase_client, &mut phase_session, &tx).await;
+        let _ = tx.send(serde_json::json!({
+            "request_id": config.request_id,
+            "status": "error",
+            "message": format!("Setup failed: {}", err),
+            "setup": setup_results,
+            "teardown": teardown_results,
+        }).to_string());
+        running_test.store(false, Ordering::SeqCst);
+        return;
+    }
+
+    let duration_secs = config.duration;
+    let warmup = Duration::from_secs(config.warmup_secs.unwrap_or(0));
+    let started = Instant::now();
+    let shared = Arc::new(Shared {
+        tls,
+        client: shared_client,
+        connections_opened: Arc::clone(&connections_opened),
+        oauth: oauth.clone(),
+        jwt_signer,
+        feeder,
+        vars: phase_session.vars.clone(),
+        request_counter: Arc::new(AtomicU64::new(0)),
+        total: Arc::new(Tally::new()),
+        warmup: Arc::new(Tally::new()),
+        measure_from: started + warmup,
+        series: TimeSeries::new(started, Duration::from_secs(config.timeseries_bucket_secs)),
+        tags: TagTallies::new(config.tag_groups.clone()),
+        url_groups: Breakdown::new(),
+        tx: tx.clone(),
+        request_id: config.request_id.clone(),
+        started,
+        end_time: duration_secs.map(|secs| started + Duration::from_secs(secs)),
+        iteration_limit: config.iterations,
+        iterations_started: AtomicU64::new(0),
+        running: AtomicBool::new(true),
+        cancel_flag: Arc::clone(&cancel_flag),
+        active_vus: AtomicU64::new(0),
+    });
+
+    // Already validated before the test started.
+    let plans = scenario::plans(&config).unwrap_or_default();
+    let named_scenarios = !config.scenarios.is_empty();
+    let mut scenario_tallies = Vec::new();
+    let mut handles = Vec::new();
+    let mut first_vu = 0;
+    for plan in plans {
+        let tally = named_scenarios.then(|| Arc::new(Tally::new()));
+        let run = Arc::new(ScenarioRun {
+            name: plan.name.clone(),
+            config: Arc::clone(&plan.config),
+            mix: RequestMix::new(&plan.config).ok().flatten().map(Arc::new),
+            record: recorder(&shared, plan.name.clone(), tally.clone()),
+            tally: tally.clone(),
+        });
+        if let Some(tally) = tally {
+            scenario_tallies.push((scenario_info(&plan), plan.start_offset, plan.duration, tally));
+        }
+        let vus = scenario::max_vus(&plan.executor) as usize;
+        handles.push(task::spawn(scenario::run(plan, Arc::clone(&shared), run, first_vu)));
+        first_vu += vus;
+    }
+
+    let start_time = tokio::time::Instant::now();
+    let mut last_snapshot = start_time;
+    let mut last_totals = (0u64, 0u64, 0u64);
+    let mut measuring = warmup.is_zero();
+
+    loop {
+        if cancel_flag.load(Ordering::SeqCst) {
+            break;
+        }
+
+        if duration_secs.is_some_and(|secs| start_time.elapsed() >= Duration::from_secs(secs)) {
+            break;
+        }
+
+        // Every virtual user stopped on its own, e.g. out of data rows.
+        if handles.iter().all(|h| h.is_finished()) {
+            break;
+        }
+
+        sleep(Duration::from_millis(200)).await;
+        shared.series.observe_vus(shared.active_vus.load(Ordering::Relaxed));
+
+        if !measuring && !shared.warming_up() {
+            measuring = true;
+            // Snapshot rates now come from the measured totals, which start at zero.
+            last_totals = (0, 0, 0);
+            let _ = tx.send(serde_json::json!({
+                "request_id": config.request_id,
+                "status": "measurement_started",
+                "elapsed_secs": start_time.elapsed().as_secs_f64(),
+                "warmup_requests": shared.warmup.metrics.lock().unwrap().total_requests,
+            }).to_string());
+        }
+
+        let interval = last_snapshot.elapsed().as_secs_f64();
+        if interval >= 1.0 {
+            let (requests, sent, received) = {
+                let tally = if measuring { &shared.total } else { &shared.warmup };
+                let m = tally.metrics.lock().unwrap();
+                (m.total_requests, m.bytes_sent, m.bytes_received)
+            };
+
+            let snapshot = serde_json::json!({
+                "request_id": config.request_id,
+                "status": "snapshot",
+                "elapsed_secs": start_time.elapsed().as_secs_f64(),
+                "warmup": !measuring,
+                "total_requests": requests,
+                "active_vus": shared.active_vus.load(Ordering::Relaxed),
+                "requests_per_sec": (requests - last_totals.0) as f64 / interval,
+                "bytes_sent": sent,
+                "bytes_received": received,
+                "sent_bytes_per_sec": (sent - last_totals.1) as f64 / interval,
+                "received_bytes_per_sec": (received - last_totals.2) as f64 / interval,
+            });
+            let _ = tx.send(snapshot.to_string());
+
+            last_snapshot = tokio::time::Instant::now();
+            last_totals = (requests, sent, received);
+        }
+    }
+
+    shared.running.store(false, Ordering::Relaxed);
+
+    for handle in handles {
+        let _ = handle.await;
+    }
+    // The load ends here; teardown time stays out of every rate below.
+    let load_elapsed = start_time.elapsed();
+    let series_elapsed = shared.started.elapsed();
+
+    let (teardown_results, _) =
+        phase::run(Phase::Teardown, &config.teardown, &config, &phase_client, &mut phase_session, &tx).await;
+
+    let mut final_metrics = shared.total.metrics.lock().unwrap();
+    let samples = shared.total.samples.lock().unwrap();
+
+    let median = calculate_median(&samples.response_times);
+    // Rates cover the measured part of the run only.
+    let elapsed_secs = load_elapsed.saturating_sub(warmup).as_secs_f64();
+    let throughput = if elapsed_secs > 0.0 {
+        final_metrics.total_requests as f64 / elapsed_secs
+    } else {
+        0.0
+    };
+
+    final_metrics.target_url = config.target.clone();
+    final_metrics.http_method = format!("{:?}", config.method);
+    final_metrics.duration_secs = elapsed_secs as u64;
+    final_metrics.concurrency = scenario::total_vus(&config);
+    final_metrics.throughput = throughput;
+    final_metrics.median_response_time = median;
+    final_metrics.median_ttfb = calculate_median(&samples.ttfb_times);
+    final_metrics.response_size = calculate_percentiles(&samples.response_sizes);
+    if elapsed_secs > 0.0 {
+        final_metrics.sent_throughput = final_metrics.bytes_sent as f64 / elapsed_secs;
+        final_metrics.received_throughput = final_metrics.bytes_received as f64 / elapsed_secs;
+    }
+    final_metrics.connections_opened = connections_opened.load(Ordering::Relaxed);
+    if let Some(cache) = &oauth {
+        final_metrics.auth_requests = cache.requests.load(Ordering::Relaxed);
+        final_metrics.auth_failures = cache.failures.load(Ordering::Relaxed);
+        final_metrics.auth_average_duration = cache.average_duration_ms();
+    }
+    final_metrics.timestamp = Local::now().format("%Y/%m/%d %H:%M:%S").to_string();
+
+    let final_metrics_msg = serde_json::json!({
+        "status": if cancel_flag.load(Ordering::SeqCst) { "aborted" } else { "final_metrics" },
+        "target_url": final_metrics.target_url,
+        "request_id": config.request_id,
+        "http_method": final_metrics.http_method,
+        "duration_secs": final_metrics.duration_secs,
+        "concurrency": final_metrics.concurrency,
+        "timestamp": final_metrics.timestamp,
+        "total_requests": final_metrics.total_requests,
+        "iterations": final_metrics.iterations,
+        "successful_requests": final_metrics.successful_requests,
+        "failed_requests": final_metrics.failed_requests,
+        "fastest_response_ms": final_metrics.fastest_response,
+        "slowest_response_ms": final_metrics.slowest_response,
+        "median_response_ms": final_metrics.median_response_time,
+        "throughput_rps": final_metrics.throughput,
+        "status_counts": final_metrics.status_counts,
+        "connections_opened": final_metrics.connections_opened,
+        "redirects": final_metrics.redirects,
+        "error_counts": final_metrics.error_counts,
+        "error_samples": final_metrics.error_samples,
+        "median_ttfb_ms": final_metrics.median_ttfb,
+        "bytes_sent": final_metrics.bytes_sent,
+        "bytes_received": final_metrics.bytes_received,
+        "request_body_bytes_uncompressed": final_metrics.request_body_bytes,
+        "bytes_received_decompressed": final_metrics.bytes_decoded,
+        "sent_bytes_per_sec": final_metrics.sent_throughput,
+        "received_bytes_per_sec": final_metrics.received_throughput,
+        "response_size_bytes": final_metrics.response_size,
+        "script_errors": has_script(&config).then_some(final_metrics.script_errors),
+        "dropped_iterations": has_arrival_rate(&config).then_some(final_metrics.dropped_iterations),
+        "auth_requests": oauth.as_ref().map(|_| serde_json::json!({
+            "total": final_metrics.auth_requests,
+            "failed": final_metrics.auth_failures,
+            "average_ms": final_metrics.auth_average_duration,
+        })),
+        "warmup": config.warmup_secs.map(|secs| {
+            let mut summary = tally_summary(&shared.warmup, warmup.min(load_elapsed).as_secs_f64());
+            if let Some(summary) = summary.as_object_mut() {
+                summary.insert("duration_secs".to_string(), secs.into());
+            }
+            summary
+        }),
+        "url_groups": (!shared.url_groups.is_empty()).then(|| shared.url_groups.report(elapsed_secs)),
+        "tags": (!shared.tags.is_empty()).then(|| shared.tags.report(elapsed_secs)),
+        "timeseries": {
+            "bucket_secs": config.timeseries_bucket_secs,
+            "buckets": shared.series.report(series_elapsed),
+        },
+        "setup": (!config.setup.is_empty()).then_some(setup_results),
+        "teardown": (!config.teardown.is_empty()).then_some(teardown_results),
+        "scenarios": named_scenarios.then(|| {
+            scenario_tallies
+                .iter()
+                .map(|((name, info), offset, duration, tally)| {
+                    // Measured time the scenario actually ran, for its throughput.
+                    let end = duration
+                        .map_or(Duration::MAX, |d| *offset + d)
+                        .min(warmup + Duration::from_secs_f64(elapsed_secs));
+                    let ran = end.saturating_sub((*offset).max(warmup));
+                    let mut summary = tally_summary(tally, ran.as_secs_f64());
+                    if let (Some(summary), Some(info)) = (summary.as_object_mut(), info.as_object()) {
+                        summary.extend(info.clone());
+                    }
+                    (name.clone(), summary)
+                })
+                .collect::<serde_json::Map<_, _>>()
+        }),
+    });
+
+    let _ = tx.send(final_metrics_msg.to_string());
+    // Only now may a new test start: users, teardown and the report are all done.
+    running_test.store(false, Ordering::SeqCst);
+}
+
+fn has_script(config: &DslConfig) -> bool {
+    config.script.is_some() || config.scenarios.iter().any(|s| s.script.is_some())
+}
+
+fn has_arrival_rate(config: &DslConfig) -> bool {
+    config
+        .scenarios
+        .iter()
+        .any(|s| matches!(s.executor, Executor::ConstantArrivalRate { .. }))
+}
+
+fn scenario_info(plan: &ScenarioPlan) -> (String, serde_json::Value) {
+    let info = serde_json::json!({
+        "executor": format!("{:?}", plan.executor),
+        "start_offset_secs": plan.start_offset.as_secs(),
+        "duration_secs": plan.duration.map(|d| d.as_secs()),
+    });
+    (plan.name.clone().unwrap_or_default(), info)
+}
+
+/// Totals of one scenario, tag or URL group, in the same units as the whole-run report.
+fn tally_summary(tally: &Tally, elapsed_secs: f64) -> serde_json::Value {
+    let m = tally.metrics.lock().unwrap();
+    let s = tally.samples.lock().unwrap();
+    let per_sec = |value: f64| if elapsed_secs > 0.0 { value / elapsed_secs } else { 0.0 };
+    serde_json::json!({
+        "total_requests": m.total_requests,
+        "iterations": m.iterations,
+        "successful_requests": m.successful_requests,
+        "failed_requests": m.failed_requests,
+        "fastest_response_ms": m.fastest_response,
+        "slowest_response_ms": m.slowest_response,
+        "median_response_ms": calculate_median(&s.response_times),
+        "response_time_ms": calculate_percentiles(&s.response_times),
+        "median_ttfb_ms": calculate_median(&s.ttfb_times),
+        "throughput_rps": per_sec(m.total_requests as f64),
+        "status_counts": m.status_counts,
+        "error_counts": m.error_counts,
+        "bytes_sent": m.bytes_sent,
+        "bytes_received": m.bytes_received,
+        "redirects": m.redirects,
+        "script_errors": m.script_errors,
+        "dropped_iterations": m.dropped_iterations,
+    })
+}
+
+/// Records into the whole-run totals and, when given, the scenario's own tally,
+/// or into the warm-up tally while the test is warming up.
+fn recorder(shared: &Arc<Shared>, scenario: Option<String>, tally: Option<Arc<Tally>>) -> RecordResult {
+    let shared = Arc::clone(shared);
+    Arc::new(move |config: &DslConfig, result| {
+        shared.series.add(&result);
+        if !shared.warming_up() {
+            shared.tags.add(&config.tags, &result);
+            shared.url_groups.add(url_group(config), &result);
+        }
+        let tallies = shared.tallies(tally.as_ref());
+        record_result(config, scenario.as_deref(), tallies, &shared.tx, result);
+    })
+}
+
+/// The group a request is reported under: its `name`, or its method and
+/// target as configured, before placeholders are filled in.
+fn url_group(config: &DslConfig) -> String {
+    match &config.url_group {
+        Some(name) => name.clone(),
+        None => format!("{:?} {}", config.method, config.target),
+    }
+}
+
+fn record_result<'a>(
+    config: &DslConfig,
+    scenario: Option<&str>,
+    tallies: impl Iterator<Item = &'a Arc<Tally>>,
+    tx: &mpsc::UnboundedSender<String>,
+    result: Result<ResponseInfo, RequestError>,
+) {
+    for tally in tallies {
+        tally.add(&result);
+    }
+
+    let message = match &result {
+        Ok(response) => serde_json::json!({
+            "request_id": config.request_id,
+            "status": "process",
+            "scenario": scenario,
+            "url_group": url_group(config),
+            "tags": (!config.tags.is_empty()).then_some(&config.tags),
+            "http_status": response.status.as_u16(),
+            "duration_ms": response.duration as f64,
+            "ttfb_ms": response.ttfb,
+            "bytes_sent": response.bytes_sent,
+            "bytes_received": response.bytes_received,
+            "request_body_bytes": response.request_body_bytes,
+            "bytes_decompressed": response.bytes_decoded,
+            "redirects": response.redirects,
+        }),
+        Err(err) => serde_json::json!({
+            "request_id": config.request_id,
+            "status": "process",
+            "scenario": scenario,
+            "url_group": url_group(config),
+            "tags": (!config.tags.is_empty()).then_some(&config.tags),
+            "http_status": "REQUEST_ERROR",
+            "error_kind": err.kind.as_str(),
+            "error": err.message,
+            "duration_ms": err.duration.map(|d| d as f64),
+        }),
+    };
+    let _ = tx.send(message.to_string());
+}
+
+impl Tally {
+    fn add(&self, result: &Result<ResponseInfo, RequestError>) {
+        match result {
+            Ok(response) => {
+                let elapsed = response.duration as f64;
+
+                {
+                    let mut s = self.samples.lock().unwrap();
+                    s.response_times.push(elapsed);
+                    s.ttfb_times.push(response.ttfb as f64);
+                    s.response_sizes.push(response.bytes_received as f64);
+                }
+
+                let mut m = self.metrics.lock().unwrap();
+                m.total_requests += 1;
+                m.successful_requests += 1;
+                m.total_duration += elapsed;
+                m.bytes_sent += response.bytes_sent;
+                m.bytes_received += response.bytes_received;
+                m.request_body_bytes += response.request_body_bytes;
+                m.bytes_decoded += response.bytes_decoded;
+                m.redirects += response.redirects as u64;
+
+                let key = response.status.as_u16().to_string();
+                *m.status_counts.entry(key).or_insert(0) += 1;
+
+                if elapsed < m.fastest_response {
+                    m.fastest_response = elapsed;
+                }
+                if elapsed > m.slowest_response {
+                    m.slowest_response = elapsed;
+                }
+            }
+            Err(err) => {
+                let elapsed = err.duration.map(|d| d as f64);
+
+                if let Some(elapsed) = elapsed {
+                    let mut s = self.samples.lock().unwrap();
+                    s.response_times.push(elapsed);
+                }
+
+                let mut m = self.metrics.lock().unwrap();
+                m.total_requests += 1;
+                m.failed_requests += 1;
+
+                *m.status_counts.entry("REQUEST_ERROR".to_string()).or_insert(0) += 1;
+                m.record_error(err);
+
+                if let Some(elapsed) = elapsed {
+                    m.total_duration += elapsed;
+                    if elapsed < m.fastest_response {
+                        m.fastest_response = elapsed;
+                    }
+                    if elapsed > m.slowest_response {
+                        m.slowest_response = elapsed;
+                    }
+                }
+            }
+        }
+    }
+}
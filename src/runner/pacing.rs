@@ -0,0 +1,56 @@
+use std::f64::consts::TAU;
+use std::time::{Duration, Instant};
+
+use tokio::time::sleep;
+
+use crate::models::dsl_model::ThinkTime;
+
+use super::KeepRunning;
+
+/// Long pauses are split into slices so a stopped test doesn't wait for them.
+const PAUSE_SLICE: Duration = Duration::from_millis(50);
+
+/// Draws one pause from the configured distribution.
+pub fn think_time(think_time: &ThinkTime) -> Duration {
+    let ms = match *think_time {
+        ThinkTime::Fixed { ms } => ms as f64,
+        ThinkTime::Uniform { min_ms, max_ms } => rand::random_range(min_ms.min(max_ms)..=max_ms.max(min_ms)) as f64,
+        ThinkTime::Normal { mean_ms, std_dev_ms } => {
+            // Box-Muller transform.
+            let (u1, u2) = (1.0 - rand::random::<f64>(), rand::random::<f64>());
+            let z = (-2.0 * u1.ln()).sqrt() * (TAU * u2).cos();
+            mean_ms as f64 + z * std_dev_ms as f64
+        }
+        ThinkTime::Exponential { mean_ms } => -(mean_ms as f64) * (1.0 - rand::random::<f64>()).ln(),
+    };
+    Duration::from_secs_f64(ms.max(0.0) / 1000.0)
+}
+
+/// How long to wait after an iteration that started at `started`: the think
+/// time, stretched so the next iteration starts no sooner than `pacing_ms` later.
+pub fn iteration_gap(think: Option<&ThinkTime>, pacing_ms: Option<u64>, started: Instant) -> Duration {
+    let think = think.map(think_time).unwrap_or_default();
+    let pacing = pacing_ms
+        .map(|ms| Duration::from_millis(ms).saturating_sub(started.elapsed()))
+        .unwrap_or_default();
+    think.max(pacing)
+}
+
+pub async fn pause(duration: Duration, keep_running: &KeepRunning) {
+    let mut remaining = duration;
+    while !remaining.is_zero() && keep_running() {
+        let slice = remaining.min(PAUSE_SLICE);
+        sleep(slice).await;
+        remaining -= slice;
+    }
+}
+
+/// `pause` for threads outside the async runtime, such as script virtual users.
+pub fn pause_blocking(duration: Duration, keep_running: &KeepRunning) {
+    let mut remaining = duration;
+    while !remaining.is_zero() && keep_running() {
+        let slice = remaining.min(PAUSE_SLICE);
+        std::thread::sleep(slice);
+        remaining -= slice;
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::client::{send_request, HttpsClient, ResponseInfo, Session};
+use crate::models::dsl_model::{DslConfig, Extraction, PhaseRequest, ResponseBodyMode};
+
+use super::mix::with_request;
+
+/// Setup or teardown.
+#[derive(Clone, Copy)]
+pub enum Phase {
+    Setup,
+    Teardown,
+}
+
+impl Phase {
+    fn as_str(self) -> &'static str {
+        match self {
+            Phase::Setup => "setup",
+            Phase::Teardown => "teardown",
+        }
+    }
+}
+
+/// Rejects extractions that can never match before the test starts.
+pub fn validate(steps: &[PhaseRequest]) -> Result<(), String> {
+    for step in steps {
+        for (name, extraction) in &step.extract {
+            if let Extraction::Json { pointer } = extraction
+                && !pointer.is_empty()
+                && !pointer.starts_with('/')
+            {
+                return Err(format!("`{}`: JSON pointer must start with `/`", name));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs the phase's requests in order, outside the load metrics. Values they
+/// extract go into `session.vars` for the following requests. Setup stops at
+/// the first failure; teardown always tries every request.
+pub async fn run(
+    phase: Phase,
+    steps: &[PhaseRequest],
+    base: &DslConfig,
+    client: &HttpsClient,
+    session: &mut Session,
+    tx: &mpsc::UnboundedSender<String>,
+) -> (Vec<Value>, Result<(), String>) {
+    let mut results = Vec::with_capacity(steps.len());
+    let mut outcome = Ok(());
+
+    for step in steps {
+        let mut config = with_request(base, &step.request);
+        config.response_body = ResponseBodyMode::Buffer;
+        let (method, target) = (format!("{:?}", step.request.method), &step.request.target);
+
+        let (result, failure) = match send_request(client, &config, session).await {
+            Ok(response) => {
+                let mut failure = (response.status.as_u16() >= 400)
+                    .then(|| format!("{} {} returned {}", method, target, response.status));
+                for (name, extraction) in &step.extract {
+                    match extract(extraction, &response) {
+                        Some(value) => {
+                            Arc::make_mut(session.vars.get_or_insert_with(Default::default)).insert(name.clone(), value);
+                        }
+                        None => {
+                            failure.get_or_insert_with(|| format!("could not extract `{}` from {}", name, target));
+                        }
+                    }
+                }
+                let result = serde_json::json!({
+                    "method": method,
+                    "target": target,
+                    "name": config.url_group,
+                    "tags": config.tags,
+                    "http_status": response.status.as_u16(),
+                    "duration_ms": response.duration,
+                    "ttfb_ms": response.ttfb,
+                    "error": failure,
+                });
+                (result, failure)
+            }
+            Err(err) => {
+                let failure = format!("{} {}: {}", method, target, err.message);
+                let result = serde_json::json!({
+                    "method": method,
+                    "target": target,
+                    "name": config.url_group,
+                    "tags": config.tags,
+                    "http_status": "REQUEST_ERROR",
+                    "error_kind": err.kind.as_str(),
+                    "duration_ms": err.duration,
+                    "error": failure,
+                });
+                (result, Some(failure))
+            }
+        };
+
+        let mut message = result.clone();
+        message["request_id"] = Value::from(base.request_id.clone());
+        message["status"] = Value::from(phase.as_str());
+        let _ = tx.send(message.to_string());
+        results.push(result);
+
+        if let Some(failure) = failure {
+            outcome = Err(failure);
+            if let Phase::Setup = phase {
+                break;
+            }
+        }
+    }
+    (results, outcome)
+}
+
+fn extract(extraction: &Extraction, response: &ResponseInfo) -> Option<String> {
+    match extraction {
+        Extraction::Json { pointer } => {
+            let json: Value = serde_json::from_slice(response.body.as_deref()?).ok()?;
+            match json.pointer(pointer)? {
+                Value::String(value) => Some(value.clone()),
+                value => Some(value.to_string()),
+            }
+        }
+        Extraction::Header { name } => response
+            .headers
+            .as_ref()?
+            .get(name.as_str())?
+            .to_str()
+            .ok()
+            .map(str::to_string),
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+use tokio::task::{self, JoinHandle};
+use tokio::time::sleep;
+
+use crate::models::dsl_model::{DslConfig, Executor, RampStage, Scenario};
+
+use super::mix::RequestMix;
+use super::pacing::{iteration_gap, pause};
+use super::vu::VirtualUser;
+use super::{KeepRunning, RecordResult, Shared, Tally};
+
+/// How often a ramping scenario adjusts its number of virtual users.
+const RAMP_TICK: Duration = Duration::from_millis(100);
+
+/// A scenario resolved against the test: its full request config and timing.
+pub struct ScenarioPlan {
+    /// `None` for the implicit scenario of a test without `scenarios`.
+    pub name: Option<String>,
+    pub executor: Executor,
+    pub start_offset: Duration,
+    /// `None` when neither the scenario nor the test has a time limit.
+    pub duration: Option<Duration>,
+    pub config: Arc<DslConfig>,
+}
+
+/// What the virtual users of one scenario share.
+pub struct ScenarioRun {
+    pub name: Option<String>,
+    pub config: Arc<DslConfig>,
+    pub mix: Option<Arc<RequestMix>>,
+    pub record: RecordResult,
+    /// The scenario's own metrics, kept only when the test has named scenarios.
+    pub tally: Option<Arc<Tally>>,
+}
+
+/// Splits the test into scenarios; a test without `scenarios` becomes a single
+/// one running `concurrency` users for `duration`.
+pub fn plans(config: &DslConfig) -> Result<Vec<ScenarioPlan>, String> {
+    if config.scenarios.is_empty() {
+        return Ok(vec![ScenarioPlan {
+            name: None,
+            executor: Executor::ConstantVus { vus: config.concurrency },
+            start_offset: Duration::ZERO,
+            duration: config.duration.map(Duration::from_secs),
+            config: Arc::new(config.clone()),
+        }]);
+    }
+
+    let mut names = HashSet::new();
+    let mut plans = Vec::with_capacity(config.scenarios.len());
+    for scenario in &config.scenarios {
+        if !names.insert(scenario.name.as_str()) {
+            return Err(format!("duplicate scenario name `{}`", scenario.name));
+        }
+        let mut duration = scenario.duration;
+        if let Some(total) = config.duration {
+            if scenario.start_offset_secs >= total {
+                return Err(format!("scenario `{}` starts after the test ends", scenario.name));
+            }
+            let length = *duration.get_or_insert(total - scenario.start_offset_secs);
+            if scenario.start_offset_secs + length > total {
+                return Err(format!("scenario `{}` runs past the test duration", scenario.name));
+            }
+        }
+        if let Executor::ConstantArrivalRate { rate_per_sec, max_vus } = scenario.executor
+            && (!rate_per_sec.is_finite()
+                || rate_per_sec <= 0.0
+                || Duration::try_from_secs_f64(1.0 / rate_per_sec).is_err()
+                || max_vus == 0)
+        {
+            return Err(format!(
+                "scenario `{}` needs a positive `rate_per_sec` and `max_vus`",
+                scenario.name
+            ));
+        }
+
+        plans.push(ScenarioPlan {
+            name: Some(scenario.name.clone()),
+            executor: scenario.executor.clone(),
+            start_offset: Duration::from_secs(scenario.start_offset_secs),
+            duration: duration.map(Duration::from_secs),
+            config: Arc::new(scenario_config(config, scenario)),
+        });
+    }
+    Ok(plans)
+}
+
+/// The test config with the scenario's request fields applied.
+fn scenario_config(base: &DslConfig, scenario: &Scenario) -> DslConfig {
+    let mut config = base.clone();
+    config.scenarios = Vec::new();
+    // A scenario with its own single request doesn't inherit the test's mix.
+    if scenario.target.is_some() || !scenario.requests.is_empty() {
+        config.requests = scenario.requests.clone();
+    }
+    if let Some(target) = &scenario.target {
+        config.target = target.clone();
+        config.url_group = scenario.url_group.clone();
+    }
+    if let Some(method) = &scenario.method {
+        config.method = method.clone();
+    }
+    if scenario.body.is_some() {
+        config.body = scenario.body.clone();
+    }
+    if scenario.query_params.is_some() {
+        config.query_params = scenario.query_params.clone();
+    }
+    if scenario.headers.is_some() {
+        config.headers = scenario.headers.clone();
+    }
+    config.tags.extend(scenario.tags.clone());
+    if scenario.script.is_some() {
+        config.script = scenario.script.clone();
+    }
+    if scenario.think_time.is_some() {
+        config.think_time = scenario.think_time;
+    }
+    if scenario.pacing_ms.is_some() {
+        config.pacing_ms = scenario.pacing_ms;
+    }
+    config
+}
+
+/// Most virtual users the executor will run at once.
+pub fn max_vus(executor: &Executor) -> u64 {
+    match executor {
+        Executor::ConstantVus { vus } => *vus,
+        Executor::RampingVus { start_vus, stages } => stages
+            .iter()
+            .map(|stage| stage.target)
+            .fold(*start_vus, u64::max),
+        Executor::ConstantArrivalRate { max_vus, .. } => *max_vus,
+    }
+}
+
+/// Virtual users across all scenarios, used for resource checks and data partitioning.
+pub fn total_vus(config: &DslConfig) -> u64 {
+    if config.scenarios.is_empty() {
+        config.concurrency
+    } else {
+        config.scenarios.iter().map(|s| max_vus(&s.executor)).sum()
+    }
+}
+
+/// Runs one scenario to completion. Its virtual users are numbered from `first_vu`.
+pub async fn run(plan: ScenarioPlan, shared: Arc<Shared>, run: Arc<ScenarioRun>, first_vu: usize) {
+    let start = shared.started + plan.start_offset;
+    let end = plan.duration.map(|duration| start + duration);
+    let scenario_running: KeepRunning = {
+        let shared = Arc::clone(&shared);
+        Arc::new(move || shared.keep_running() && end.is_none_or(|end| Instant::now() < end))
+    };
+
+    pause(start.saturating_duration_since(Instant::now()), &scenario_running).await;
+
+    match plan.executor {
+        Executor::ConstantVus { vus } => {
+            let handles: Vec<_> = (0..vus as usize)
+                .map(|i| {
+                    let vu = VirtualUser::new(first_vu + i, &shared, &run, Arc::clone(&scenario_running));
+                    spawn_looping_vu(vu, &shared, &run)
+                })
+                .collect();
+            for handle in handles {
+                let _ = handle.await;
+            }
+        }
+        Executor::RampingVus { start_vus, ref stages } => {
+            let max_vus = max_vus(&plan.executor) as usize;
+            ramping_vus(start_vus, stages, max_vus, first_vu, &shared, &run, &scenario_running).await;
+        }
+        Executor::ConstantArrivalRate { rate_per_sec, max_vus } => {
+            arrival_rate(rate_per_sec, max_vus as usize, first_vu, &shared, &run, &scenario_running).await;
+        }
+    }
+}
+
+/// Loops the user until it's told to stop, then hands it back.
+fn spawn_looping_vu(mut vu: VirtualUser, shared: &Arc<Shared>, run: &Arc<ScenarioRun>) -> JoinHandle<VirtualUser> {
+    let shared = Arc::clone(shared);
+    let run = Arc::clone(run);
+
+    task::spawn(async move {
+        let keep_running = vu.keep_running();
+        shared.active_vus.fetch_add(1, Ordering::Relaxed);
+        while keep_running() {
+            let started = Instant::now();
+            if !vu.iterate().await {
+                break;
+            }
+            let gap = iteration_gap(run.config.think_time.as_ref(), run.config.pacing_ms, started);
+            pause(gap, &keep_running).await;
+        }
+        shared.active_vus.fetch_sub(1, Ordering::Relaxed);
+        vu
+    })
+}
+
+/// A ramping user and the flag that retires it.
+struct RampingVu {
+    stop: Arc<AtomicBool>,
+    vu: VirtualUser,
+}
+
+async fn ramping_vus(
+    start_vus: u64,
+    stages: &[RampStage],
+    max_vus: usize,
+    first_vu: usize,
+    shared: &Arc<Shared>,
+    run: &Arc<ScenarioRun>,
+    scenario_running: &KeepRunning,
+) {
+    let started = Instant::now();
+    let ramp_length = Duration::from_secs(stages.iter().map(|stage| stage.duration_secs).sum());
+    let mut active: Vec<(Arc<AtomicBool>, JoinHandle<VirtualUser>)> = Vec::new();
+    let mut retiring: Vec<(Arc<AtomicBool>, JoinHandle<VirtualUser>)> = Vec::new();
+    // Retired users that finished their last iteration. A returning user takes
+    // one of these back, keeping its `vu_id` and iteration count, so no two
+    // running users share an index and `UniquePerVu` rows aren't replayed.
+    let mut idle: Vec<RampingVu> = Vec::new();
+    let mut created = 0;
+
+    while scenario_running() && !shared.iterations_exhausted() {
+        let mut index = 0;
+        while index < retiring.len() {
+            if retiring[index].1.is_finished() {
+                let (stop, handle) = retiring.swap_remove(index);
+                if let Ok(vu) = handle.await {
+                    idle.push(RampingVu { stop, vu });
+                }
+            } else {
+                index += 1;
+            }
+        }
+
+        let target = ramp_target(start_vus, stages, started.elapsed()) as usize;
+        while active.len() < target {
+            let RampingVu { stop, vu } = match idle.pop() {
+                Some(user) => {
+                    user.stop.store(false, Ordering::Relaxed);
+                    user
+                }
+                // Every index is taken until a retiring user finishes.
+                None if created == max_vus => break,
+                None => {
+                    let stop = Arc::new(AtomicBool::new(false));
+                    let keep_running: KeepRunning = {
+                        let (scenario_running, stop) = (Arc::clone(scenario_running), Arc::clone(&stop));
+                        Arc::new(move || scenario_running() && !stop.load(Ordering::Relaxed))
+                    };
+                    created += 1;
+                    let vu = VirtualUser::new(first_vu + created - 1, shared, run, keep_running);
+                    RampingVu { stop, vu }
+                }
+            };
+            active.push((stop, spawn_looping_vu(vu, shared, run)));
+        }
+        // The newest users leave first, after finishing their current iteration.
+        while active.len() > target {
+            if let Some((stop, handle)) = active.pop() {
+                stop.store(true, Ordering::Relaxed);
+                retiring.push((stop, handle));
+            }
+        }
+
+        // Past the last stage nothing new starts, so stop once every user is done.
+        if started.elapsed() >= ramp_length && active.iter().all(|(_, handle)| handle.is_finished()) {
+            break;
+        }
+        sleep(RAMP_TICK).await;
+    }
+
+    for (_, handle) in active.into_iter().chain(retiring) {
+        let _ = handle.await;
+    }
+}
+
+/// Users wanted `elapsed` into the ramp, moving linearly towards each stage's target.
+fn ramp_target(start_vus: u64, stages: &[RampStage], elapsed: Duration) -> u64 {
+    let mut from = start_vus;
+    let mut stage_start = Duration::ZERO;
+    for stage in stages {
+        let length = Duration::from_secs(stage.duration_secs);
+        if elapsed < stage_start + length {
+            let progress = (elapsed - stage_start).as_secs_f64() / length.as_secs_f64();
+            let value = from as f64 + (stage.target as f64 - from as f64) * progress;
+            return value.round() as u64;
+        }
+        from = stage.target;
+        stage_start += length;
+    }
+    from
+}
+
+async fn arrival_rate(
+    rate_per_sec: f64,
+    max_vus: usize,
+    first_vu: usize,
+    shared: &Arc<Shared>,
+    run: &Arc<ScenarioRun>,
+    scenario_running: &KeepRunning,
+) {
+    let interval = Duration::from_secs_f64(1.0 / rate_per_sec);
+    let (idle_tx, mut idle_rx) = mpsc::unbounded_channel::<VirtualUser>();
+    // Users out of data rows or iterations; once all of them are, the scenario is over.
+    let retired = Arc::new(AtomicUsize::new(0));
+    let mut created = 0;
+    let mut handles = Vec::new();
+    let mut next_start = Instant::now();
+
+    while scenario_running() && retired.load(Ordering::Relaxed) < max_vus && !shared.iterations_exhausted() {
+        let vu = match idle_rx.try_recv() {
+            Ok(vu) => Some(vu),
+            Err(_) if created < max_vus => {
+                created += 1;
+                Some(VirtualUser::new(first_vu + created - 1, shared, run, Arc::clone(scenario_running)))
+            }
+            Err(_) => None,
+        };
+
+        match vu {
+            Some(mut vu) => {
+                let (shared, idle_tx, retired) = (Arc::clone(shared), idle_tx.clone(), Arc::clone(&retired));
+                handles.retain(|h: &JoinHandle<()>| !h.is_finished());
+                handles.push(task::spawn(async move {
+                    shared.active_vus.fetch_add(1, Ordering::Relaxed);
+                    let more = vu.iterate().await;
+                    shared.active_vus.fetch_sub(1, Ordering::Relaxed);
+                    if more {
+                        let _ = idle_tx.send(vu);
+                    } else {
+                        retired.fetch_add(1, Ordering::Relaxed);
+                    }
+                }));
+            }
+            // Every user is still busy: the target can't keep up with this rate.
+            None => {
+                for tally in shared.tallies(run.tally.as_ref()) {
+                    tally.metrics.lock().unwrap().dropped_iterations += 1;
+                }
+            }
+        }
+
+        next_start += interval;
+        pause(next_start.saturating_duration_since(Instant::now()), scenario_running).await;
+    }
+
+    for handle in handles {
+        let _ = handle.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(scenarios: serde_json::Value) -> DslConfig {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r",
+            "name": "n",
+            "target": "http://127.0.0.1:1/",
+            "method": "GET",
+            "concurrency": 1,
+            "duration": 10,
+            "scenarios": scenarios,
+        }))
+        .unwrap()
+    }
+
+    fn arrival_rate(rate_per_sec: f64) -> serde_json::Value {
+        serde_json::json!([{
+            "name": "a",
+            "executor": { "type": "ConstantArrivalRate", "rate_per_sec": rate_per_sec, "max_vus": 1 },
+        }])
+    }
+
+    #[test]
+    fn arrival_rate_must_fit_an_interval() {
+        assert!(plans(&config(arrival_rate(0.5))).is_ok());
+        assert!(plans(&config(arrival_rate(0.0))).is_err());
+        assert!(plans(&config(arrival_rate(-1.0))).is_err());
+        assert!(plans(&config(arrival_rate(1e-20))).is_err());
+    }
+
+    #[test]
+    fn scenarios_must_fit_the_test() {
+        let overrun = serde_json::json!([{
+            "name": "a",
+            "executor": { "type": "ConstantVus", "vus": 1 },
+            "start_offset_secs": 5,
+            "duration": 6,
+        }]);
+        assert!(plans(&config(overrun)).is_err());
+
+        let duplicate = serde_json::json!([
+            { "name": "a", "executor": { "type": "ConstantVus", "vus": 1 } },
+            { "name": "a", "executor": { "type": "ConstantVus", "vus": 1 } },
+        ]);
+        assert!(plans(&config(duplicate)).is_err());
+    }
+
+    #[test]
+    fn ramp_moves_linearly_between_stages() {
+        let stages = [
+            RampStage { duration_secs: 10, target: 10 },
+            RampStage { duration_secs: 10, target: 0 },
+        ];
+        assert_eq!(ramp_target(0, &stages, Duration::ZERO), 0);
+        assert_eq!(ramp_target(0, &stages, Duration::from_secs(5)), 5);
+        assert_eq!(ramp_target(0, &stages, Duration::from_secs(10)), 10);
+        assert_eq!(ramp_target(0, &stages, Duration::from_secs(15)), 5);
+        assert_eq!(ramp_target(0, &stages, Duration::from_secs(30)), 0);
+    }
+
+    #[test]
+    fn ramp_without_stages_holds_the_start() {
+        assert_eq!(ramp_target(4, &[], Duration::from_secs(3)), 4);
+        assert_eq!(max_vus(&Executor::RampingVus { start_vus: 4, stages: Vec::new() }), 4);
+    }
+}
@@ -0,0 +1,468 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use tokio::runtime::Handle;
+
+use crate::client::error::RequestError;
+use crate::client::{send_request, HttpsClient, ResponseInfo, Session};
+use crate::models::dsl_model::{Body, DataRow, DslConfig, HttpMethod, ResponseBodyMode, ScriptConfig};
+
+use super::pacing::{pause_blocking, think_time};
+use super::scenario::ScenarioRun;
+use super::KeepRunning;
+
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_STRING_SIZE: usize = 1024 * 1024;
+const MAX_COLLECTION_SIZE: usize = 10_000;
+
+/// Compiles the script once, so syntax errors reject the test before it starts.
+pub fn compile_script(script: &mut ScriptConfig) -> Result<(), String> {
+    if script.ast.is_none() {
+        let ast = sandboxed_engine(script.max_operations)
+            .compile(&script.source)
+            .map_err(|e| e.to_string())?;
+        script.ast = Some(Arc::new(ast));
+    }
+    Ok(())
+}
+
+/// Engine without `import` or `eval`, and with bounded resources.
+fn sandboxed_engine(max_operations: u64) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.disable_symbol("import");
+    engine.set_max_operations(max_operations);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_COLLECTION_SIZE);
+    engine.set_max_map_size(MAX_COLLECTION_SIZE);
+    engine
+}
+
+pub type ScriptEvent = Arc<dyn Fn(&str, String) + Send + Sync>;
+
+/// State shared between a virtual user's iterations and the functions bound into the script.
+struct Bindings {
+    client: Arc<HttpsClient>,
+    run: Arc<ScenarioRun>,
+    session: Mutex<Session>,
+    runtime: Handle,
+    requests: AtomicU32,
+    max_requests: u32,
+}
+
+/// A virtual user driven by the scenario's script.
+pub struct ScriptVu {
+    engine: Engine,
+    ast: Arc<AST>,
+    bindings: Arc<Bindings>,
+    vu_id: usize,
+    /// Setup values, exposed to the script as `vars`.
+    vars: Map,
+    /// Receives `("log", message)` from `print`/`debug` and `("error", message)`
+    /// when an iteration fails.
+    event: ScriptEvent,
+}
+
+impl ScriptVu {
+    /// Must be called from within the Tokio runtime the requests will run on.
+    pub fn new(
+        ast: Arc<AST>,
+        run: &Arc<ScenarioRun>,
+        client: Arc<HttpsClient>,
+        session: Session,
+        event: ScriptEvent,
+        keep_running: KeepRunning,
+    ) -> Self {
+        let script = run.config.script.as_ref();
+        let vu_id = session.vu_id;
+        let vars = session
+            .vars
+            .iter()
+            .flat_map(|vars| vars.iter())
+            .map(|(k, v)| (k.as_str().into(), Dynamic::from(v.clone())))
+            .collect();
+        let bindings = Arc::new(Bindings {
+            client,
+            run: Arc::clone(run),
+            session: Mutex::new(session),
+            runtime: Handle::current(),
+            requests: AtomicU32::new(0),
+            max_requests: script.map_or(0, |s| s.max_requests),
+        });
+        let engine = vu_engine(script.map_or(u64::MAX, |s| s.max_operations), &bindings, &keep_running, &event);
+        Self { engine, ast, bindings, vu_id, vars, event }
+    }
+
+    /// Runs the script once. Blocks while requests are in flight, so call it
+    /// outside the async runtime (e.g. `spawn_blocking`).
+    pub fn iterate(&self, iteration: u64, row: Option<DataRow>) {
+        {
+            let mut session = self.bindings.session.lock().unwrap();
+            session.iteration = iteration;
+            session.row = row.clone();
+        }
+        self.bindings.requests.store(0, Ordering::Relaxed);
+
+        let mut scope = Scope::new();
+        scope.push_constant("vu_id", self.vu_id as i64);
+        scope.push_constant("iteration", iteration as i64);
+        let data: Map = row
+            .iter()
+            .flat_map(|row| row.iter())
+            .map(|(k, v)| (k.as_str().into(), Dynamic::from(v.clone())))
+            .collect();
+        scope.push_constant("data", data);
+        scope.push_constant("vars", self.vars.clone());
+
+        match self.engine.run_ast_with_scope(&mut scope, &self.ast) {
+            Ok(()) => {}
+            // Stopped because the test or scenario ended.
+            Err(err) if matches!(*err, EvalAltResult::ErrorTerminated(..)) => {}
+            Err(err) => (self.event)("error", err.to_string()),
+        }
+    }
+}
+
+fn vu_engine(max_operations: u64, bindings: &Arc<Bindings>, keep_running: &KeepRunning, event: &ScriptEvent) -> Engine {
+    let mut engine = sandboxed_engine(max_operations);
+
+    let keep_running_progress = Arc::clone(keep_running);
+    engine.on_progress(move |_| {
+        if keep_running_progress() {
+            None
+        } else {
+            Some(Dynamic::UNIT)
+        }
+    });
+
+    let print_event = Arc::clone(event);
+    engine.on_print(move |message| print_event("log", message.to_string()));
+    let debug_event = Arc::clone(event);
+    engine.on_debug(move |message, _, _| debug_event("log", message.to_string()));
+
+    let b = Arc::clone(bindings);
+    engine.register_fn("request", move || perform(&b, Map::new()));
+    let b = Arc::clone(bindings);
+    engine.register_fn("request", move |options: Map| perform(&b, options));
+
+    let keep_running_sleep = Arc::clone(keep_running);
+    engine.register_fn("sleep", move |ms: i64| {
+        pause_blocking(Duration::from_millis(ms.max(0) as u64), &keep_running_sleep);
+    });
+
+    let keep_running_think = Arc::clone(keep_running);
+    let configured = bindings.run.config.think_time;
+    engine.register_fn("think", move || {
+        if let Some(configured) = &configured {
+            pause_blocking(think_time(configured), &keep_running_think);
+        }
+    });
+
+    engine
+}
+
+/// Sends one request, records it like any other and hands the response to the script.
+fn perform(bindings: &Bindings, options: Map) -> Result<Map, Box<EvalAltResult>> {
+    if bindings.requests.fetch_add(1, Ordering::Relaxed) >= bindings.max_requests {
+        return Err(format!("Request limit of {} per iteration reached", bindings.max_requests).into());
+    }
+    let base = bindings.run.mix.as_ref().map_or(&bindings.run.config, |mix| mix.pick());
+    let config = request_config(base, options)?;
+
+    let mut session = std::mem::take(&mut *bindings.session.lock().unwrap());
+    let result = bindings
+        .runtime
+        .block_on(send_request(&bindings.client, &config, &mut session));
+    *bindings.session.lock().unwrap() = session;
+
+    let response = response_map(&result);
+    (bindings.run.record)(&config, result);
+    Ok(response)
+}
+
+/// Applies the options given to `request(#{...})` on top of the test config.
+fn request_config(base: &DslConfig, options: Map) -> Result<DslConfig, Box<EvalAltResult>> {
+    let mut config = base.clone();
+    config.response_body = ResponseBodyMode::Buffer;
+    let mut content_type = None;
+    let mut url_group = None;
+
+    for (key, value) in options {
+        match key.as_str() {
+            "url" => {
+                config.target = value.into_string()?;
+                // The base request's group no longer describes this URL.
+                config.url_group = None;
+            }
+            "name" => url_group = Some(value.into_string()?),
+            "method" => {
+                let method = value.into_string()?.to_ascii_uppercase();
+                config.method = serde_json::from_value::<HttpMethod>(serde_json::Value::String(method.clone()))
+                    .map_err(|_| format!("Unsupported method: {}", method))?;
+            }
+            "headers" => {
+                let headers = config.headers.get_or_insert_with(Default::default);
+                for (name, value) in value.try_cast::<Map>().ok_or("`headers` must be a map")? {
+                    if name.eq_ignore_ascii_case("content-type") {
+                        content_type = Some(value.to_string());
+                    }
+                    headers.insert(name.to_string(), value.to_string());
+                }
+            }
+            "query" => {
+                let params = config.query_params.get_or_insert_with(Default::default);
+                for (name, value) in value.try_cast::<Map>().ok_or("`query` must be a map")? {
+                    params.insert(name.to_string(), value.to_string());
+                }
+            }
+            "tags" => {
+                for (name, value) in value.try_cast::<Map>().ok_or("`tags` must be a map")? {
+                    config.tags.insert(name.to_string(), value.to_string());
+                }
+            }
+            "json" => {
+                let json = rhai::serde::from_dynamic::<serde_json::Value>(&value)?;
+                config.body = Some(Body::Json(json));
+            }
+            "body" => {
+                config.body = Some(Body::Raw {
+                    content_type: String::new(),
+                    data: value.into_string()?,
+                });
+            }
+            other => return Err(format!("Unknown request option: {}", other).into()),
+        }
+    }
+
+    if url_group.is_some() {
+        config.url_group = url_group;
+    }
+    if let Some(Body::Raw { content_type: body_type, .. }) = config.body.as_mut()
+        && body_type.is_empty()
+    {
+        // The body sends its own Content-Type; keeping the header would send it twice.
+        if let Some(headers) = config.headers.as_mut().filter(|_| content_type.is_some()) {
+            headers.retain(|name, _| !name.eq_ignore_ascii_case("content-type"));
+        }
+        *body_type = content_type.unwrap_or_else(|| "text/plain".to_string());
+    }
+    Ok(config)
+}
+
+fn response_map(result: &Result<ResponseInfo, RequestError>) -> Map {
+    let mut map = Map::new();
+    match result {
+        Ok(response) => {
+            map.insert("status".into(), (response.status.as_u16() as i64).into());
+            map.insert("duration_ms".into(), (response.duration as i64).into());
+            map.insert("ttfb_ms".into(), (response.ttfb as i64).into());
+
+            let mut headers = Map::new();
+            for (name, value) in response.headers.iter().flatten() {
+                let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+                headers
+                    .entry(name.as_str().into())
+                    .and_modify(|existing| *existing = format!("{}, {}", existing, value).into())
+                    .or_insert_with(|| value.into());
+            }
+            map.insert("headers".into(), headers.into());
+
+            let body = response.body.as_deref().unwrap_or_default();
+            let json = serde_json::from_slice::<serde_json::Value>(body)
+                .ok()
+                .and_then(|json| rhai::serde::to_dynamic(json).ok())
+                .unwrap_or(Dynamic::UNIT);
+            map.insert("body".into(), String::from_utf8_lossy(body).to_string().into());
+            map.insert("json".into(), json);
+        }
+        Err(err) => {
+            map.insert("status".into(), 0_i64.into());
+            map.insert("error".into(), err.message.clone().into());
+            map.insert("error_kind".into(), err.kind.as_str().into());
+        }
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, AtomicUsize};
+
+    use hyper::header::CONTENT_TYPE;
+
+    use crate::client::connector::TlsConnector;
+    use crate::client::{build_client, build_request};
+    use crate::template::TemplateContext;
+
+    fn config(extra: serde_json::Value) -> DslConfig {
+        let mut config = serde_json::json!({
+            "request_id": "r",
+            "name": "n",
+            "target": "http://127.0.0.1:1/base",
+            "method": "GET",
+            "concurrency": 1,
+            "duration": 1,
+        });
+        config.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(config).unwrap()
+    }
+
+    fn script(source: &str) -> ScriptConfig {
+        serde_json::from_value(serde_json::json!({ "source": source })).unwrap()
+    }
+
+    fn options(value: serde_json::Value) -> Map {
+        rhai::serde::to_dynamic(value).unwrap().cast::<Map>()
+    }
+
+    #[test]
+    fn compile_rejects_syntax_errors() {
+        assert!(compile_script(&mut script("let x = ;")).is_err());
+        let mut valid = script("let x = 1 + 2;");
+        assert!(compile_script(&mut valid).is_ok());
+        assert!(valid.ast.is_some());
+    }
+
+    #[test]
+    fn compile_rejects_eval_and_imports() {
+        assert!(compile_script(&mut script(r#"eval("1 + 1")"#)).is_err());
+        assert!(compile_script(&mut script(r#"import "other" as other;"#)).is_err());
+    }
+
+    #[test]
+    fn max_operations_stops_infinite_loops() {
+        let engine = sandboxed_engine(10_000);
+        let err = engine.run("loop { }").unwrap_err();
+        assert!(matches!(*err, EvalAltResult::ErrorTooManyOperations(..)), "{}", err);
+    }
+
+    #[test]
+    fn collection_limits_apply() {
+        let engine = sandboxed_engine(u64::MAX);
+        let err = engine.run("let a = []; loop { a.push(1); }").unwrap_err();
+        assert!(matches!(*err, EvalAltResult::ErrorDataTooLarge(..)), "{}", err);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn request_limit_fails_the_next_request() {
+        let source = "for i in 0..5 { let r = request(); if r.status != 0 { throw \"expected a transport error\"; } }";
+        let mut config = config(serde_json::json!({}));
+        let mut script_config = script(source);
+        script_config.max_requests = 2;
+        compile_script(&mut script_config).unwrap();
+        config.script = Some(script_config.clone());
+
+        let recorded = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&recorded);
+        let run = Arc::new(ScenarioRun {
+            name: None,
+            config: Arc::new(config.clone()),
+            mix: None,
+            record: Arc::new(move |_: &DslConfig, _| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }),
+            tally: None,
+        });
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&errors);
+        let event: ScriptEvent = Arc::new(move |kind: &str, message: String| {
+            if kind == "error" {
+                sink.lock().unwrap().push(message);
+            }
+        });
+        let client = Arc::new(build_client(
+            &config,
+            TlsConnector::from_config(None).unwrap(),
+            Arc::new(AtomicU64::new(0)),
+        ));
+        let vu = ScriptVu::new(
+            script_config.ast.unwrap(),
+            &run,
+            client,
+            Session::default(),
+            event,
+            Arc::new(|| true),
+        );
+
+        tokio::task::spawn_blocking(move || vu.iterate(0, None)).await.unwrap();
+
+        assert_eq!(recorded.load(Ordering::Relaxed), 2);
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("Request limit of 2 per iteration reached"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn request_options_override_the_base_request() {
+        let base = config(serde_json::json!({ "headers": { "X-Base": "1" }, "query_params": { "a": "1" } }));
+        let request = request_config(
+            &base,
+            options(serde_json::json!({
+                "url": "http://127.0.0.1:1/other",
+                "method": "post",
+                "headers": { "X-Extra": "2" },
+                "query": { "b": 2 },
+                "json": { "id": 7, "tags": ["x"] },
+            })),
+        )
+        .unwrap();
+
+        assert_eq!(request.target, "http://127.0.0.1:1/other");
+        assert!(matches!(request.method, HttpMethod::POST));
+        let headers = request.headers.unwrap();
+        assert_eq!(headers["X-Base"], "1");
+        assert_eq!(headers["X-Extra"], "2");
+        let params = request.query_params.unwrap();
+        assert_eq!((params["a"].as_str(), params["b"].as_str()), ("1", "2"));
+        assert!(matches!(
+            request.body,
+            Some(Body::Json(json)) if json == serde_json::json!({ "id": 7, "tags": ["x"] })
+        ));
+        assert_eq!(request.response_body, ResponseBodyMode::Buffer);
+    }
+
+    #[test]
+    fn raw_body_takes_the_content_type_header() {
+        let base = config(serde_json::json!({}));
+        let request = request_config(
+            &base,
+            options(serde_json::json!({ "body": "<a/>", "headers": { "content-type": "application/xml" } })),
+        )
+        .unwrap();
+        assert!(matches!(
+            &request.body,
+            Some(Body::Raw { content_type, data }) if content_type == "application/xml" && data == "<a/>"
+        ));
+        let built = build_request(&request, &TemplateContext::default()).unwrap();
+        let content_types: Vec<_> = built.headers().get_all(CONTENT_TYPE).iter().collect();
+        assert_eq!(content_types, ["application/xml"]);
+
+        let request = request_config(&base, options(serde_json::json!({ "body": "plain" }))).unwrap();
+        assert!(matches!(
+            request.body,
+            Some(Body::Raw { content_type, .. }) if content_type == "text/plain"
+        ));
+    }
+
+    #[test]
+    fn invalid_request_options_are_errors() {
+        let base = config(serde_json::json!({}));
+        for options_value in [
+            serde_json::json!({ "method": "FETCH" }),
+            serde_json::json!({ "headers": "X-A: 1" }),
+            serde_json::json!({ "query": 1 }),
+            serde_json::json!({ "url": 1 }),
+            serde_json::json!({ "timeout": 5 }),
+        ] {
+            assert!(request_config(&base, options(options_value.clone())).is_err(), "{}", options_value);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+use crate::client::error::RequestError;
+use crate::client::ResponseInfo;
+
+use super::breakdown::Breakdown;
+
+/// Measured results broken down by tag values, keyed like `endpoint=checkout,tier=premium`.
+pub struct TagTallies {
+    groups: Vec<Vec<String>>,
+    breakdown: Breakdown,
+}
+
+impl TagTallies {
+    pub fn new(groups: Vec<Vec<String>>) -> Self {
+        Self {
+            groups,
+            breakdown: Breakdown::new(),
+        }
+    }
+
+    /// Counts the result once per group whose tags the request carries all of;
+    /// without configured groups, once per tag.
+    pub fn add(&self, tags: &HashMap<String, String>, result: &Result<ResponseInfo, RequestError>) {
+        let keys: Vec<String> = if self.groups.is_empty() {
+            tags.iter().map(|(name, value)| format!("{}={}", name, value)).collect()
+        } else {
+            self.groups
+                .iter()
+                .filter_map(|group| {
+                    group
+                        .iter()
+                        .map(|name| tags.get(name).map(|value| format!("{}={}", name, value)))
+                        .collect::<Option<Vec<_>>>()
+                        .map(|pairs| pairs.join(","))
+                })
+                .collect()
+        };
+
+        for key in keys {
+            self.breakdown.add(key, result);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.breakdown.is_empty()
+    }
+
+    pub fn report(&self, elapsed_secs: f64) -> serde_json::Map<String, serde_json::Value> {
+        self.breakdown.report(elapsed_secs)
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::client::error::RequestError;
+use crate::client::ResponseInfo;
+use crate::utils::stats::{calculate_median, calculate_percentiles};
+
+/// Results of the whole run split into fixed-length intervals, so the report
+/// shows how the target behaved over time and not only on average.
+pub struct TimeSeries {
+    started: Instant,
+    bucket: Duration,
+    buckets: Mutex<Vec<Bucket>>,
+}
+
+#[derive(Default)]
+struct Bucket {
+    requests: u64,
+    failed: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    status_counts: HashMap<String, u64>,
+    response_times: Vec<f64>,
+    /// Most virtual users seen active during the interval.
+    active_vus: u64,
+}
+
+impl TimeSeries {
+    pub fn new(started: Instant, bucket: Duration) -> Self {
+        Self {
+            started,
+            bucket,
+            buckets: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Runs `f` on the interval the current instant falls into.
+    fn with_current(&self, f: impl FnOnce(&mut Bucket)) {
+        let index = (self.started.elapsed().as_secs_f64() / self.bucket.as_secs_f64()) as usize;
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() <= index {
+            buckets.resize_with(index + 1, Bucket::default);
+        }
+        f(&mut buckets[index]);
+    }
+
+    pub fn add(&self, result: &Result<ResponseInfo, RequestError>) {
+        self.with_current(|bucket| {
+            bucket.requests += 1;
+            match result {
+                Ok(response) => {
+                    bucket.bytes_sent += response.bytes_sent;
+                    bucket.bytes_received += response.bytes_received;
+                    bucket.response_times.push(response.duration as f64);
+                    *bucket
+                        .status_counts
+                        .entry(response.status.as_u16().to_string())
+                        .or_insert(0) += 1;
+                }
+                Err(err) => {
+                    bucket.failed += 1;
+                    bucket.response_times.extend(err.duration.map(|d| d as f64));
+                    *bucket.status_counts.entry("REQUEST_ERROR".to_string()).or_insert(0) += 1;
+                }
+            }
+        });
+    }
+
+    pub fn observe_vus(&self, active: u64) {
+        self.with_current(|bucket| bucket.active_vus = bucket.active_vus.max(active));
+    }
+
+    /// One entry per interval up to `elapsed`; the last one may be shorter.
+    pub fn report(&self, elapsed: Duration) -> Vec<serde_json::Value> {
+        let buckets = self.buckets.lock().unwrap();
+        let count = buckets
+            .len()
+            .max((elapsed.as_secs_f64() / self.bucket.as_secs_f64()).ceil() as usize);
+        let empty = Bucket::default();
+
+        (0..count)
+            .map(|i| {
+                let bucket = buckets.get(i).unwrap_or(&empty);
+                let start = self.bucket * i as u32;
+                let length = elapsed.saturating_sub(start).min(self.bucket).as_secs_f64();
+                let per_sec = |value: u64| if length > 0.0 { value as f64 / length } else { 0.0 };
+                serde_json::json!({
+                    "start_secs": start.as_secs_f64(),
+                    "duration_secs": length,
+                    "total_requests": bucket.requests,
+                    "failed_requests": bucket.failed,
+                    "requests_per_sec": per_sec(bucket.requests),
+                    "error_rate": if bucket.requests > 0 { bucket.failed as f64 / bucket.requests as f64 } else { 0.0 },
+                    "status_counts": bucket.status_counts,
+                    "median_response_ms": calculate_median(&bucket.response_times),
+                    "response_time_ms": calculate_percentiles(&bucket.response_times),
+                    "active_vus": bucket.active_vus,
+                    "bytes_sent": bucket.bytes_sent,
+                    "bytes_received": bucket.bytes_received,
+                    "sent_bytes_per_sec": per_sec(bucket.bytes_sent),
+                    "received_bytes_per_sec": per_sec(bucket.bytes_received),
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,140 @@
+use std::sync::Arc;
+
+use cookie_store::CookieStore;
+use tokio::task;
+
+use crate::client::jwt::JwtSession;
+use crate::client::{build_client, send_request, HttpsClient, Session};
+
+use super::scenario::ScenarioRun;
+use super::script::{ScriptEvent, ScriptVu};
+use super::{KeepRunning, Shared};
+
+/// One virtual user: its connection pool, session and iteration count.
+pub struct VirtualUser {
+    index: usize,
+    client: Arc<HttpsClient>,
+    shared: Arc<Shared>,
+    run: Arc<ScenarioRun>,
+    driver: Driver,
+    iteration: u64,
+    keep_running: KeepRunning,
+}
+
+enum Driver {
+    /// Sends the configured request, or one from the mix, each iteration.
+    Request(Box<Session>),
+    /// Runs the scenario's script each iteration.
+    Script(Arc<ScriptVu>),
+}
+
+impl VirtualUser {
+    pub fn new(index: usize, shared: &Arc<Shared>, run: &Arc<ScenarioRun>, keep_running: KeepRunning) -> Self {
+        let client = match &shared.client {
+            Some(client) => Arc::clone(client),
+            None => Arc::new(build_client(
+                &run.config,
+                shared.tls.clone(),
+                Arc::clone(&shared.connections_opened),
+            )),
+        };
+        let session = Session {
+            vu_id: index + 1,
+            counter: Arc::clone(&shared.request_counter),
+            oauth: shared.oauth.clone(),
+            jwt: shared.jwt_signer.clone().map(JwtSession::new),
+            cookies: run.config.cookie_jar.then(CookieStore::default),
+            vars: shared.vars.clone(),
+            ..Default::default()
+        };
+
+        let script = run.config.script.as_ref().and_then(|script| script.ast.clone());
+        let driver = match script {
+            Some(ast) => {
+                let event = script_event(index + 1, shared, run);
+                Driver::Script(Arc::new(ScriptVu::new(
+                    ast,
+                    run,
+                    Arc::clone(&client),
+                    session,
+                    event,
+                    Arc::clone(&keep_running),
+                )))
+            }
+            None => Driver::Request(Box::new(session)),
+        };
+
+        Self {
+            index,
+            client,
+            shared: Arc::clone(shared),
+            run: Arc::clone(run),
+            driver,
+            iteration: 0,
+            keep_running,
+        }
+    }
+
+    /// The check this user was created with, telling it when to stop.
+    pub fn keep_running(&self) -> KeepRunning {
+        Arc::clone(&self.keep_running)
+    }
+
+    /// Runs one iteration; `false` once this user is out of data rows or iterations.
+    pub async fn iterate(&mut self) -> bool {
+        if self.run.config.iterations_per_vu.is_some_and(|limit| self.iteration >= limit)
+            || !self.shared.claim_iteration()
+        {
+            return false;
+        }
+        let row = match &self.shared.feeder {
+            Some(feeder) => match feeder.next_row(self.index, self.iteration) {
+                Some(row) => Some(row),
+                None => return false,
+            },
+            None => None,
+        };
+        let iteration = self.iteration;
+        self.iteration += 1;
+
+        match &mut self.driver {
+            Driver::Request(session) => {
+                session.row = row;
+                session.iteration = iteration;
+                let request = self.run.mix.as_ref().map_or(&self.run.config, |mix| mix.pick());
+                let result = send_request(&self.client, request, session).await;
+                (self.run.record)(request, result);
+            }
+            Driver::Script(script) => {
+                let script = Arc::clone(script);
+                let _ = task::spawn_blocking(move || script.iterate(iteration, row)).await;
+            }
+        }
+
+        for tally in self.shared.tallies(self.run.tally.as_ref()) {
+            tally.metrics.lock().unwrap().iterations += 1;
+        }
+        true
+    }
+}
+
+/// Streams `print` output and script errors, counting the latter.
+fn script_event(vu_id: usize, shared: &Arc<Shared>, run: &ScenarioRun) -> ScriptEvent {
+    let shared = Arc::clone(shared);
+    let tally = run.tally.clone();
+    let scenario = run.name.clone();
+    Arc::new(move |kind: &str, message: String| {
+        if kind == "error" {
+            for tally in shared.tallies(tally.as_ref()) {
+                tally.metrics.lock().unwrap().script_errors += 1;
+            }
+        }
+        let _ = shared.tx.send(serde_json::json!({
+            "request_id": shared.request_id,
+            "status": format!("script_{}", kind),
+            "scenario": scenario,
+            "vu_id": vu_id,
+            "message": message,
+        }).to_string());
+    })
+}
@@ -0,0 +1,63 @@
+use crate::template::TemplateContext;
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD as BASE64_URL};
+use base64::Engine;
+use chrono::Utc;
+use md5::Md5;
+use rand::distr::Alphanumeric;
+use rand::Rng;
+use sha2::{Digest, Sha256, Sha512};
+use std::fmt::Write;
+use url::form_urlencoded;
+
+/// Longest string `random_string` will produce.
+const MAX_RANDOM_STRING: usize = 65_536;
+
+/// Evaluates a built-in variable or function; `None` for unknown names or
+/// invalid arguments.
+pub fn call(name: &str, args: &[String], ctx: &TemplateContext) -> Option<String> {
+    let value = match (name, args) {
+        ("vu_id", []) => ctx.vu_id.to_string(),
+        ("iteration", []) => ctx.iteration.to_string(),
+        ("counter", []) => ctx.counter.to_string(),
+        ("uuid", []) => uuid::Uuid::new_v4().to_string(),
+        ("timestamp", []) => Utc::now().timestamp().to_string(),
+        ("timestamp", [format]) => timestamp(format)?,
+        ("random_int", [min, max]) => {
+            let (min, max) = (min.parse::<i64>().ok()?, max.parse::<i64>().ok()?);
+            if min > max {
+                return None;
+            }
+            rand::random_range(min..=max).to_string()
+        }
+        ("random_string", [len]) => {
+            let len = len.parse::<usize>().ok()?.min(MAX_RANDOM_STRING);
+            rand::rng().sample_iter(Alphanumeric).take(len).map(char::from).collect()
+        }
+        ("concat", parts) => parts.concat(),
+        ("base64", [value]) => BASE64.encode(value),
+        ("base64url", [value]) => BASE64_URL.encode(value),
+        ("url_encode", [value]) => form_urlencoded::byte_serialize(value.as_bytes()).collect(),
+        ("md5", [value]) => hex::encode(Md5::digest(value.as_bytes())),
+        ("sha256", [value]) => hex::encode(Sha256::digest(value.as_bytes())),
+        ("sha512", [value]) => hex::encode(Sha512::digest(value.as_bytes())),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// `unix`, `unix_ms`, `iso8601`, `rfc2822` or a strftime pattern such as `%Y-%m-%d`.
+fn timestamp(format: &str) -> Option<String> {
+    let now = Utc::now();
+    let value = match format {
+        "unix" => now.timestamp().to_string(),
+        "unix_ms" => now.timestamp_millis().to_string(),
+        "iso8601" | "rfc3339" => now.to_rfc3339(),
+        "rfc2822" => now.to_rfc2822(),
+        pattern => {
+            let mut out = String::new();
+            write!(out, "{}", now.format(pattern)).ok()?;
+            out
+        }
+    };
+    Some(value)
+}
@@ -0,0 +1,339 @@
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+mod functions;
+
+/// Values available to `{{...}}` placeholders while building one request.
+#[derive(Default, Clone, Copy)]
+pub struct TemplateContext<'a> {
+    /// 1-based index of the virtual user.
+    pub vu_id: usize,
+    /// 0-based iteration of the virtual user.
+    pub iteration: u64,
+    /// Test-wide request sequence number, starting at 1.
+    pub counter: u64,
+    /// Current data row, when the test has a dataset.
+    pub row: Option<&'a HashMap<String, String>>,
+    /// Values extracted by the setup phase.
+    pub vars: Option<&'a HashMap<String, String>>,
+}
+
+impl TemplateContext<'_> {
+    /// A data column, or else a setup value, called `name`.
+    fn lookup(&self, name: &str) -> Option<&String> {
+        self.row
+            .and_then(|row| row.get(name))
+            .or_else(|| self.vars.and_then(|vars| vars.get(name)))
+    }
+}
+
+/// Replaces every `{{...}}` in `input`. A placeholder holds a data column,
+/// a setup value, a built-in variable or a function call such as `{{random_int(1, 100)}}`;
+/// anything that doesn't resolve is left untouched, so text that only looks
+/// like a placeholder is sent as is.
+pub fn render<'s>(input: &'s str, ctx: &TemplateContext) -> Cow<'s, str> {
+    if !input.contains("{{") {
+        return Cow::Borrowed(input);
+    }
+
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(open) = rest.find("{{") {
+        let Some(close) = rest[open + 2..].find("}}") else {
+            break;
+        };
+        let placeholder = &rest[open..open + 2 + close + 2];
+        out.push_str(&rest[..open]);
+        match evaluate(placeholder[2..placeholder.len() - 2].trim(), ctx) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(placeholder),
+        }
+        rest = &rest[open + placeholder.len()..];
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// Whether any placeholder in `input` reads one of `names`, such as values
+/// the setup phase will extract once the test runs.
+pub fn references(input: &str, names: &HashSet<&str>) -> bool {
+    let mut rest = input;
+    while let Some(open) = rest.find("{{") {
+        let Some(close) = rest[open + 2..].find("}}") else {
+            break;
+        };
+        let source = rest[open + 2..open + 2 + close].trim();
+        let mut parser = Parser { src: source, pos: 0 };
+        let found = match parser.expr() {
+            Some(expr) if parser.at_end() => expr.references(names),
+            _ => names.contains(source),
+        };
+        if found {
+            return true;
+        }
+        rest = &rest[open + 2 + close + 2..];
+    }
+    false
+}
+
+/// Renders every string (keys included) inside a JSON value.
+pub fn render_json(value: &Value, ctx: &TemplateContext) -> Value {
+    match value {
+        Value::String(s) => Value::String(render(s, ctx).into_owned()),
+        Value::Array(items) => Value::Array(items.iter().map(|v| render_json(v, ctx)).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(k, v)| (render(k, ctx).into_owned(), render_json(v, ctx)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn evaluate(source: &str, ctx: &TemplateContext) -> Option<String> {
+    let mut parser = Parser { src: source, pos: 0 };
+    match parser.expr() {
+        Some(expr) if parser.at_end() => expr.eval(ctx),
+        // Column names that aren't identifiers, e.g. `{{first name}}`.
+        _ => ctx.lookup(source).cloned(),
+    }
+}
+
+enum Expr<'a> {
+    Literal(String),
+    /// A bare name (`vu_id`, a data column) or a call (`uuid()`).
+    Call {
+        name: &'a str,
+        args: Vec<Expr<'a>>,
+        parens: bool,
+    },
+}
+
+impl Expr<'_> {
+    fn references(&self, names: &HashSet<&str>) -> bool {
+        match self {
+            Expr::Literal(_) => false,
+            Expr::Call { name, args, parens } => {
+                (!parens && names.contains(name)) || args.iter().any(|arg| arg.references(names))
+            }
+        }
+    }
+
+    fn eval(&self, ctx: &TemplateContext) -> Option<String> {
+        match self {
+            Expr::Literal(value) => Some(value.clone()),
+            Expr::Call { name, args, parens } => {
+                // Data columns and setup values take precedence over built-in variables.
+                if !parens && let Some(value) = ctx.lookup(name) {
+                    return Some(value.clone());
+                }
+                let args = args.iter().map(|arg| arg.eval(ctx)).collect::<Option<Vec<_>>>()?;
+                functions::call(name, &args, ctx)
+            }
+        }
+    }
+}
+
+/// Parses `name`, `name(arg, ...)`, `'text'`, `"text"` and numbers.
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn expr(&mut self) -> Option<Expr<'a>> {
+        self.skip_whitespace();
+        let c = self.peek()?;
+        if c == '\'' || c == '"' {
+            return self.string(c).map(Expr::Literal);
+        }
+        if c.is_ascii_digit() || c == '-' {
+            let token = self.take_while(|c| c.is_ascii_digit() || c == '.' || c == '-');
+            return Some(Expr::Literal(token.to_string()));
+        }
+        if !(c.is_alphabetic() || c == '_') {
+            return None;
+        }
+
+        let name = self.take_while(|c| c.is_alphanumeric() || c == '_' || c == '.' || c == '-');
+        self.skip_whitespace();
+        if self.peek() != Some('(') {
+            return Some(Expr::Call { name, args: Vec::new(), parens: false });
+        }
+        self.pos += 1;
+
+        let mut args = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(')') {
+            self.pos += 1;
+        } else {
+            loop {
+                args.push(self.expr()?);
+                self.skip_whitespace();
+                match self.peek()? {
+                    ',' => self.pos += 1,
+                    ')' => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => return None,
+                }
+            }
+        }
+        Some(Expr::Call { name, args, parens: true })
+    }
+
+    fn string(&mut self, quote: char) -> Option<String> {
+        self.pos += quote.len_utf8();
+        let mut value = String::new();
+        let mut chars = self.src[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => value.push(chars.next()?.1),
+                c if c == quote => {
+                    self.pos += i + c.len_utf8();
+                    return Some(value);
+                }
+                c => value.push(c),
+            }
+        }
+        None
+    }
+
+    fn take_while(&mut self, accept: impl Fn(char) -> bool) -> &'a str {
+        let start = self.pos;
+        while let Some(c) = self.peek().filter(|c| accept(*c)) {
+            self.pos += c.len_utf8();
+        }
+        &self.src[start..self.pos]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.take_while(char::is_whitespace);
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.pos == self.src.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx_with<'a>(
+        row: Option<&'a HashMap<String, String>>,
+        vars: Option<&'a HashMap<String, String>>,
+    ) -> TemplateContext<'a> {
+        TemplateContext {
+            vu_id: 3,
+            iteration: 7,
+            counter: 42,
+            row,
+            vars,
+        }
+    }
+
+    fn map(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn text_without_placeholders_is_borrowed() {
+        assert!(matches!(render("/users/1", &ctx_with(None, None)), Cow::Borrowed("/users/1")));
+    }
+
+    #[test]
+    fn built_in_variables() {
+        let ctx = ctx_with(None, None);
+        assert_eq!(render("vu={{vu_id}} it={{ iteration }} n={{counter}}", &ctx), "vu=3 it=7 n=42");
+    }
+
+    #[test]
+    fn unresolved_placeholders_are_left_as_is() {
+        let ctx = ctx_with(None, None);
+        assert_eq!(render("{{unknown}}", &ctx), "{{unknown}}");
+        assert_eq!(render("{{unknown_fn(1)}}", &ctx), "{{unknown_fn(1)}}");
+        assert_eq!(render("{{ not a column }}", &ctx), "{{ not a column }}");
+        assert_eq!(render("{{concat('a', }}", &ctx), "{{concat('a', }}");
+        assert_eq!(render("open {{vu_id", &ctx), "open {{vu_id");
+    }
+
+    #[test]
+    fn columns_that_are_not_identifiers() {
+        let row = map(&[("first name", "Ada"), ("e-mail", "ada@example.com")]);
+        let ctx = ctx_with(Some(&row), None);
+        assert_eq!(render("{{first name}} <{{ e-mail }}>", &ctx), "Ada <ada@example.com>");
+    }
+
+    #[test]
+    fn columns_take_precedence_over_setup_values_and_built_ins() {
+        let row = map(&[("vu_id", "from-row"), ("token", "row-token")]);
+        let vars = map(&[("token", "setup-token"), ("session", "setup-session")]);
+        let ctx = ctx_with(Some(&row), Some(&vars));
+        assert_eq!(render("{{vu_id}} {{token}} {{session}}", &ctx), "from-row row-token setup-session");
+        // Bare names inside calls resolve the same way.
+        assert_eq!(render("{{concat(vu_id, '-', counter)}}", &ctx), "from-row-42");
+    }
+
+    #[test]
+    fn quoted_strings_and_escapes() {
+        let ctx = ctx_with(None, None);
+        assert_eq!(render(r#"{{concat('it\'s', " a \"quote\"", ' back\\slash')}}"#, &ctx), r#"it's a "quote" back\slash"#);
+        assert_eq!(render("{{concat('a, b', '(c)')}}", &ctx), "a, b(c)");
+    }
+
+    #[test]
+    fn nested_calls() {
+        let ctx = ctx_with(None, None);
+        assert_eq!(render("{{base64(concat('user', ':', 'pass'))}}", &ctx), "dXNlcjpwYXNz");
+        assert_eq!(render("{{url_encode(concat('a b', '&c'))}}", &ctx), "a+b%26c");
+    }
+
+    #[test]
+    fn random_int_ranges() {
+        let ctx = ctx_with(None, None);
+        for _ in 0..50 {
+            let value: i64 = render("{{random_int(-5, -1)}}", &ctx).parse().unwrap();
+            assert!((-5..=-1).contains(&value));
+        }
+        assert_eq!(render("{{random_int(4, 4)}}", &ctx), "4");
+        assert_eq!(render("{{random_int(10, 1)}}", &ctx), "{{random_int(10, 1)}}");
+        assert_eq!(render("{{random_int(1, x)}}", &ctx), "{{random_int(1, x)}}");
+        assert_eq!(render("{{random_int(1.5, 3)}}", &ctx), "{{random_int(1.5, 3)}}");
+    }
+
+    #[test]
+    fn timestamp_patterns() {
+        let ctx = ctx_with(None, None);
+        let date = render("{{timestamp('%Y-%m-%d')}}", &ctx);
+        assert_eq!(date.len(), 10);
+        assert_eq!(date.matches('-').count(), 2);
+        assert_eq!(render("{{timestamp('%Q')}}", &ctx), "{{timestamp('%Q')}}");
+    }
+
+    #[test]
+    fn references_find_names_inside_placeholders() {
+        let names: HashSet<&str> = ["loc", "first name"].into_iter().collect();
+        assert!(references("{{loc}}", &names));
+        assert!(references("https://api/{{ concat(loc, '/items') }}?id={{uuid()}}", &names));
+        assert!(references("{{first name}}", &names));
+        assert!(!references("https://api/{{uuid()}}/{{'loc'}}", &names));
+        assert!(!references("{{loc(1)}} and {{location}}", &names));
+        assert!(!references("no placeholders", &names));
+    }
+
+    #[test]
+    fn json_keys_and_values_are_rendered() {
+        let ctx = ctx_with(None, None);
+        let value = serde_json::json!({ "user-{{vu_id}}": ["{{counter}}", 1, null] });
+        assert_eq!(render_json(&value, &ctx), serde_json::json!({ "user-3": ["42", 1, null] }));
+    }
+}
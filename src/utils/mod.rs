@@ -1 +1,2 @@
-pub mod hardware;
\ No newline at end of file
+pub mod hardware;
+pub mod stats;
\ No newline at end of file
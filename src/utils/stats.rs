@@ -0,0 +1,46 @@
+use serde::Serialize;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+pub fn calculate_median(data: &[f64]) -> f64 {
+    let mut sorted = data.to_owned();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let len = sorted.len();
+    if len == 0 {
+        return 0.0;
+    }
+    if len.is_multiple_of(2) {
+        (sorted[len / 2 - 1] + sorted[len / 2]) / 2.0
+    } else {
+        sorted[len / 2]
+    }
+}
+
+/// Nearest-rank percentiles over an unsorted sample.
+pub fn calculate_percentiles(data: &[f64]) -> Percentiles {
+    let mut sorted = data.to_owned();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    if sorted.is_empty() {
+        return Percentiles::default();
+    }
+
+    let rank = |p: f64| {
+        let idx = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+        sorted[idx.clamp(1, sorted.len()) - 1]
+    };
+
+    Percentiles {
+        p50: rank(50.0),
+        p90: rank(90.0),
+        p95: rank(95.0),
+        p99: rank(99.0),
+        max: sorted[sorted.len() - 1],
+    }
+}
@@ -1,24 +1,29 @@
 use actix::prelude::*;
 use actix_web::{web, HttpRequest, HttpResponse, Error};
 use actix_web_actors::ws;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use crate::utils::hardware::get_hardware_info;
-use crate::models::dsl_model::DslConfig;
-use std::sync::{Arc, Mutex};
+use crate::models::dsl_model::{Auth, DslConfig, Scenario};
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::{Duration, Instant};
 
 use tokio::sync::mpsc;
 use tokio::task;
-use tokio::time::sleep;
 use tokio_stream::wrappers::UnboundedReceiverStream;
-use chrono::Local;
-use hyper_tls::HttpsConnector;
-use hyper::Client;
 
-use crate::client::{send_request, HttpsClient};
-use crate::models::metrics::Metrics;
+use crate::client::build_request;
+use crate::client::body::load_payloads;
+use crate::client::connector::TlsConnector;
+use crate::client::error::{RequestError, RequestErrorKind};
+use crate::client::jwt::JwtSigner;
+use crate::runner::{run_test, TestControl};
+use crate::runner::feeder::load_dataset;
+use crate::runner::mix::{with_request, RequestMix};
+use crate::runner::phase;
+use crate::runner::scenario;
+use crate::runner::script::compile_script;
+use crate::template::{references, TemplateContext};
 
 use std::sync::atomic::{AtomicUsize};
 
@@ -37,7 +42,7 @@ impl WsSession {
         }
     }
 }
-static ACTIVE_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+pub static ACTIVE_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
 const MAX_CONNECTIONS: usize = 1;
 
 impl Actor for WsSession {
@@ -71,6 +76,24 @@ impl Actor for WsSession {
 }
 
 fn validate_config(config: &DslConfig) -> Result<(), String> {
+    if config.duration.is_none() && config.iterations.is_none() && config.iterations_per_vu.is_none() {
+        return Err("`duration` is required unless `iterations` or `iterations_per_vu` is set".to_string());
+    }
+
+    if let (Some(warmup), Some(duration)) = (config.warmup_secs, config.duration)
+        && warmup >= duration
+    {
+        return Err("`warmup_secs` must be shorter than `duration`".to_string());
+    }
+
+    if config.timeseries_bucket_secs == 0 {
+        return Err("`timeseries_bucket_secs` must be at least 1".to_string());
+    }
+
+    if config.tag_groups.iter().any(|group| group.is_empty()) {
+        return Err("Each entry of `tag_groups` needs at least one tag name".to_string());
+    }
+
     let (cpu_cores, _total_mem_kb, free_mem_kb) = get_hardware_info();
 
     let min_ram_kb = 500 * 1024;
@@ -80,18 +103,20 @@ fn validate_config(config: &DslConfig) -> Result<(), String> {
         return Err(format!("Insufficient free RAM: {:.2} MB", free_mem_kb as f64 / 1024.0));
     }
 
-    if config.concurrency > cpu_cores * 3 {
+    let concurrency = scenario::total_vus(config);
+
+    if concurrency > cpu_cores * 3 {
         return Err(format!(
             "Concurrency {} is too high for CPU cores {}",
-            config.concurrency, cpu_cores
+            concurrency, cpu_cores
         ));
     }
 
-    if (config.concurrency as u64) * ram_per_thread_kb > free_mem_kb {
+    if concurrency * ram_per_thread_kb > free_mem_kb {
         return Err(format!(
             "Concurrency {} requires more RAM than available. Required: {:.2} MB, Available: {:.2} MB",
-            config.concurrency,
-            (config.concurrency as u64 * ram_per_thread_kb) as f64 / 1024.0,
+            concurrency,
+            (concurrency * ram_per_thread_kb) as f64 / 1024.0,
             free_mem_kb as f64 / 1024.0
         ));
     }
@@ -99,6 +124,100 @@ fn validate_config(config: &DslConfig) -> Result<(), String> {
     Ok(())
 }
 
+/// Loads data files, payloads and scripts into `config` and checks everything
+/// else the test needs, in declaration order. The error is the message to send.
+fn prepare_test(config: &mut DslConfig) -> Result<(TlsConnector, Option<Arc<JwtSigner>>), serde_json::Value> {
+    let error = |message: String| serde_json::json!({ "status": "error", "message": message });
+    let invalid_request = |err: RequestError| serde_json::json!({
+        "status": "error",
+        "message": format!("Invalid request configuration: {}", err),
+        "error_kind": err.kind.as_str(),
+    });
+
+    let total_vus = scenario::total_vus(config);
+    if let Some(data) = config.data.as_mut() {
+        load_dataset(data, total_vus).map_err(|err_msg| error(format!("Invalid data: {}", err_msg)))?;
+    }
+
+    config
+        .script
+        .iter_mut()
+        .chain(config.scenarios.iter_mut().filter_map(|s| s.script.as_mut()))
+        .try_for_each(compile_script)
+        .map_err(|err_msg| error(format!("Invalid script: {}", err_msg)))?;
+
+    let scenario_bodies = config.scenarios.iter_mut().flat_map(|scenario| {
+        let Scenario { body, requests, .. } = scenario;
+        body.iter_mut().chain(requests.iter_mut().filter_map(|r| r.request.body.as_mut()))
+    });
+    let phase_bodies = config
+        .setup
+        .iter_mut()
+        .chain(config.teardown.iter_mut())
+        .filter_map(|step| step.request.body.as_mut());
+    config
+        .body
+        .iter_mut()
+        .chain(config.requests.iter_mut().filter_map(|r| r.request.body.as_mut()))
+        .chain(scenario_bodies)
+        .chain(phase_bodies)
+        .try_for_each(load_payloads)
+        .map_err(invalid_request)?;
+
+    phase::validate(&config.setup)
+        .and_then(|_| phase::validate(&config.teardown))
+        .map_err(|err_msg| error(format!("Invalid setup or teardown: {}", err_msg)))?;
+
+    let plans = scenario::plans(config).map_err(|err_msg| error(format!("Invalid scenarios: {}", err_msg)))?;
+    let mixes = plans
+        .iter()
+        .map(|plan| RequestMix::new(&plan.config))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err_msg| error(format!("Invalid request mix: {}", err_msg)))?;
+
+    let first_row = config.data.as_ref().and_then(|d| d.rows.as_ref()).and_then(|rows| rows.0.first().cloned());
+    let template_ctx = TemplateContext {
+        vu_id: 1,
+        counter: 1,
+        row: first_row.as_deref(),
+        ..Default::default()
+    };
+    let phase_configs: Vec<_> = config
+        .setup
+        .iter()
+        .chain(&config.teardown)
+        .map(|step| with_request(config, &step.request))
+        .collect();
+    // Values extracted by setup aren't known yet, so a URL built
+    // from them can only be checked once the test runs.
+    let setup_values: HashSet<&str> = config
+        .setup
+        .iter()
+        .flat_map(|step| step.extract.keys())
+        .map(String::as_str)
+        .collect();
+    plans
+        .iter()
+        .map(|plan| plan.config.as_ref())
+        .chain(mixes.iter().flatten().flat_map(|mix| mix.configs()))
+        .chain(&phase_configs)
+        .try_for_each(|c| match build_request(c, &template_ctx) {
+            Err(err) if err.kind == RequestErrorKind::InvalidUrl && references(&c.target, &setup_values) => Ok(()),
+            built => built.map(|_| ()),
+        })
+        .map_err(invalid_request)?;
+
+    let tls = TlsConnector::from_config(config.tls.as_ref()).map_err(error)?;
+    let jwt_signer = match &config.auth {
+        Some(auth @ Auth::Jwt { .. }) => Some(Arc::new(
+            JwtSigner::new(auth).map_err(|err| error(format!("JWT signing failed: {}", err.message)))?,
+        )),
+        _ => None,
+    };
+
+    Ok((tls, jwt_signer))
+}
+
 impl StreamHandler<String> for WsSession {
     fn handle(&mut self, msg: String, ctx: &mut Self::Context) {
         ctx.text(msg);
@@ -109,7 +228,7 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         match msg {
             Ok(ws::Message::Text(text)) => {
-               let trimmed = text.trim();
+                let trimmed = text.trim();
                 let unquoted = trimmed.trim_matches('"');
 
                 if unquoted == "abort" {
@@ -128,244 +247,127 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
                     }
                     return;
                 }
-                match serde_json::from_str::<DslConfig>(&text) {
-                    Ok(config) => {
-                        match validate_config(&config) {
-                            Ok(()) => {
-                                if self.running_test.load(Ordering::SeqCst) {
-                            ctx.text(serde_json::json!({
-                                "status": "error",
-                                "message": "A test is already running. Please wait for it to finish before starting another."
-                            }).to_string());
-                            return;
-
-                        }
-                        self.running_test.store(true, Ordering::SeqCst);
-                        self.cancel_flag.store(false, Ordering::SeqCst);
-
-                        let (cpu_cores, total_mem_kb, free_mem_kb) = get_hardware_info();
-                                let tx = match &self.tx {
-                                    Some(sender) => sender.clone(),
-                                    None => {
-                                        ctx.text(serde_json::json!({
-                                            "status": "error",
-                                            "message": "Internal channel not initialized"
-                                        }).to_string());
-                                        return;
-                                    }
-                                };
-
-                                ctx.text(serde_json::json!({
-                                    "status": "start-config",
-                                    "config": {
-                                        "request_id": config.request_id,
-                                        "name": config.name,
-                                        "target": config.target,
-                                        "method": format!("{:?}", config.method),
-                                        "concurrency": config.concurrency,
-                                        "duration": config.duration,
-                                        "auth": config.auth.as_ref().map(|a| format!("{:?}", a)).unwrap_or_else(|| "None".to_string()),
-                                        "body": config.body.as_ref().map(|b| format!("{:?}", b)),
-                                        "query_params": config.query_params,
-                                        "headers": config.headers,
-                                        "hardware_info": {
-                                            "cpu_cores": cpu_cores,
-                                            "total_ram_mb": total_mem_kb as f64 / 1024.0,
-                                            "free_ram_mb": free_mem_kb as f64 / 1024.0,
-                                        }
-                                    }
-                                }).to_string());
-
-                                let https = HttpsConnector::new();
-                                let client: HttpsClient = Client::builder().build::<_, hyper::Body>(https);
-                                let client = Arc::new(client);
-                                let config = Arc::new(config);
-
-
-                                let metrics = Arc::new(Mutex::new(Metrics {
-                                    fastest_response: f64::MAX,
-                                    slowest_response: f64::MIN,
-                                    status_counts: HashMap::new(),
-                                    ..Default::default()
-                                }));
-
-
-                                let response_times = Arc::new(Mutex::new(Vec::new()));
-                                let running = Arc::new(AtomicBool::new(true));
-                                let mut handles = Vec::new();
-
-
-                                let duration_secs = config.duration;
-                                let end_time = Instant::now() + Duration::from_secs(duration_secs);
-
-
-                                for _ in 0..config.concurrency {
-                                    let client = Arc::clone(&client);
-                                    let config = Arc::clone(&config);
-                                    let metrics = Arc::clone(&metrics);
-                                    let response_times = Arc::clone(&response_times);
-                                    let cancel_flag = self.cancel_flag.clone();
-                                    let running = Arc::clone(&running);
-                                    let tx = tx.clone();
-
-                                    let handle = task::spawn(async move {
-                                        while running.load(Ordering::Relaxed) && Instant::now() < end_time &&  ACTIVE_CONNECTIONS.load(Ordering::SeqCst) > 0 && !cancel_flag.load(Ordering::SeqCst)  {
-                                            let result = send_request(&client, &config).await;
-
-                                            match result {
-                                                Ok((status, duration)) => {
-                                                    let elapsed = duration as f64;
-
-                                                    {
-                                                        let mut rt = response_times.lock().unwrap();
-                                                        rt.push(elapsed);
-                                                    }
-
-                                                    let mut m = metrics.lock().unwrap();
-                                                    m.total_requests += 1;
-                                                    m.successful_requests += 1;
-                                                    m.total_duration += elapsed;
-
-                                                    let status_code = status.as_u16();
-                                                    let message = serde_json::json!({
-                                                        "request_id": config.request_id,
-                                                        "status": "process",
-                                                        "http_status": status_code,
-                                                        "duration_ms": elapsed,
-                                                    });
-                                                    let _ = tx.send(message.to_string());
-
-                                                    let key = status_code.to_string();
-                                                    *m.status_counts.entry(key).or_insert(0) += 1;
-
-                                                    if elapsed < m.fastest_response {
-                                                        m.fastest_response = elapsed;
-                                                    }
-                                                    if elapsed > m.slowest_response {
-                                                        m.slowest_response = elapsed;
-                                                    }
-                                                }
-                                                Err((err_msg, duration)) => {
-                                                    let elapsed = duration as f64;
-
-                                                    {
-                                                        let mut rt = response_times.lock().unwrap();
-                                                        rt.push(elapsed);
-                                                    }
-
-                                                    let mut m = metrics.lock().unwrap();
-                                                    m.total_requests += 1;
-                                                    m.failed_requests += 1;
-                                                    m.total_duration += elapsed;
-
-                                                    let message = serde_json::json!({
-                                                        "request_id": config.request_id,
-                                                        "status": "process",
-                                                        "http_status": "REQUEST_ERROR",
-                                                        "error": err_msg,
-                                                        "duration_ms": elapsed,
-                                                    });
-                                                    let _ = tx.send(message.to_string());
-
-                                                    *m.status_counts.entry("REQUEST_ERROR".to_string()).or_insert(0) += 1;
-
-                                                    if elapsed < m.fastest_response {
-                                                        m.fastest_response = elapsed;
-                                                    }
-                                                    if elapsed > m.slowest_response {
-                                                        m.slowest_response = elapsed;
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    });
-
-                                    handles.push(handle);
-                                }
-
-                                let metrics = Arc::clone(&metrics);
-                                let response_times = Arc::clone(&response_times);
-                                let config = Arc::clone(&config);
-
-                                let cancel_flag = Arc::clone(&self.cancel_flag);
-                                let running = Arc::clone(&self.running_test);
-
-                                task::spawn(async move {
-                                    let start_time = tokio::time::Instant::now();
-
-                                    loop {
-                                        if cancel_flag.load(Ordering::SeqCst) {
-                                            break;
-                                        }
-
-                                        if start_time.elapsed() >= Duration::from_secs(duration_secs) {
-                                            break;
-                                        }
-
-                                        sleep(Duration::from_millis(200)).await;
-                                    }
-
-                                    running.store(false, Ordering::Relaxed);
-
-                                    for handle in handles {
-                                        let _ = handle.await;
-                                    }
-
-                                    let mut final_metrics = metrics.lock().unwrap();
-                                    let response_times = response_times.lock().unwrap();
-
-                                    let median = calculate_median(&response_times);
-                                    let elapsed_secs = start_time.elapsed().as_secs_f64();
-                                    let throughput = if elapsed_secs > 0.0 {
-                                        final_metrics.total_requests as f64 / elapsed_secs
-                                    } else {
-                                        0.0
-                                    };
-
-                                    final_metrics.target_url = config.target.clone();
-                                    final_metrics.http_method = format!("{:?}", config.method);
-                                    final_metrics.duration_secs = elapsed_secs as u64;
-                                    final_metrics.concurrency = config.concurrency;
-                                    final_metrics.throughput = throughput;
-                                    final_metrics.median_response_time = median;
-                                    final_metrics.timestamp = Local::now().format("%Y/%m/%d %H:%M:%S").to_string();
-
-                                    let final_metrics_msg = serde_json::json!({
-                                        "status": if cancel_flag.load(Ordering::SeqCst) { "aborted" } else { "final_metrics" },
-                                        "target_url": final_metrics.target_url,
-                                        "request_id": config.request_id,
-                                        "http_method": final_metrics.http_method,
-                                        "duration_secs": final_metrics.duration_secs,
-                                        "concurrency": final_metrics.concurrency,
-                                        "timestamp": final_metrics.timestamp,
-                                        "total_requests": final_metrics.total_requests,
-                                        "successful_requests": final_metrics.successful_requests,
-                                        "failed_requests": final_metrics.failed_requests,
-                                        "fastest_response_ms": final_metrics.fastest_response,
-                                        "slowest_response_ms": final_metrics.slowest_response,
-                                        "median_response_ms": final_metrics.median_response_time,
-                                        "throughput_rps": final_metrics.throughput,
-                                        "status_counts": final_metrics.status_counts,
-                                    });
-
-                                    let _ = tx.send(final_metrics_msg.to_string());
-                                });
-                            }
-                            Err(err_msg) => {
-                                ctx.text(serde_json::json!({
-                                    "status": "error",
-                                    "message": err_msg
-                                }).to_string());
-                            }
-                        }
-                    }
+                let mut config = match serde_json::from_str::<DslConfig>(&text) {
+                    Ok(config) => config,
                     Err(_) => {
                         ctx.text(serde_json::json!({
                             "status": "error",
                             "message": "Invalid config format"
                         }).to_string());
+                        return;
                     }
+                };
+                if let Err(err_msg) = validate_config(&config) {
+                    ctx.text(serde_json::json!({
+                        "status": "error",
+                        "message": err_msg
+                    }).to_string());
+                    return;
+                }
+                if self.running_test.load(Ordering::SeqCst) {
+                    ctx.text(serde_json::json!({
+                        "status": "error",
+                        "message": "A test is already running. Please wait for it to finish before starting another."
+                    }).to_string());
+                    return;
                 }
+                let (tls, jwt_signer) = match prepare_test(&mut config) {
+                    Ok(prepared) => prepared,
+                    Err(error) => {
+                        ctx.text(error.to_string());
+                        return;
+                    }
+                };
+                let tx = match &self.tx {
+                    Some(sender) => sender.clone(),
+                    None => {
+                        ctx.text(serde_json::json!({
+                            "status": "error",
+                            "message": "Internal channel not initialized"
+                        }).to_string());
+                        return;
+                    }
+                };
+                self.running_test.store(true, Ordering::SeqCst);
+                self.cancel_flag.store(false, Ordering::SeqCst);
+
+                let (cpu_cores, total_mem_kb, free_mem_kb) = get_hardware_info();
+
+                ctx.text(serde_json::json!({
+                    "status": "start-config",
+                    "config": {
+                        "request_id": config.request_id,
+                        "name": config.name,
+                        "target": config.target,
+                        "method": format!("{:?}", config.method),
+                        "concurrency": config.concurrency,
+                        "duration": config.duration,
+                        "iterations": config.iterations,
+                        "iterations_per_vu": config.iterations_per_vu,
+                        "warmup_secs": config.warmup_secs,
+                        "timeseries_bucket_secs": config.timeseries_bucket_secs,
+                        "auth": config.auth.as_ref().map(|a| format!("{:?}", a)).unwrap_or_else(|| "None".to_string()),
+                        "body": config.body.as_ref().map(|b| format!("{:?}", b)),
+                        "query_params": config.query_params,
+                        "headers": config.headers,
+                        "connection": config.connection.as_ref().map(|c| format!("{:?}", c)),
+                        "compression": config.compression.as_ref().map(|c| format!("{:?}", c)),
+                        "cookie_jar": config.cookie_jar,
+                        "redirects": config.redirects.as_ref().map(|r| format!("{:?}", r)),
+                        "data": config.data.as_ref().map(|d| serde_json::json!({
+                            "rows": d.rows.as_ref().map(|rows| rows.0.len()).unwrap_or(0),
+                            "selection": format!("{:?}", d.selection),
+                            "on_exhausted": format!("{:?}", d.on_exhausted),
+                        })),
+                        "requests": config.requests.iter().map(|r| serde_json::json!({
+                            "weight": r.weight,
+                            "method": format!("{:?}", r.request.method),
+                            "target": r.request.target,
+                            "name": r.request.name,
+                        })).collect::<Vec<_>>(),
+                        "url_group": config.url_group,
+                        "tags": config.tags,
+                        "tag_groups": config.tag_groups,
+                        "scenarios": config.scenarios.iter().map(|s| serde_json::json!({
+                            "name": s.name,
+                            "executor": format!("{:?}", s.executor),
+                            "start_offset_secs": s.start_offset_secs,
+                            "duration": s.duration,
+                        })).collect::<Vec<_>>(),
+                        "setup": config.setup.len(),
+                        "teardown": config.teardown.len(),
+                        "think_time": config.think_time.as_ref().map(|t| format!("{:?}", t)),
+                        "pacing_ms": config.pacing_ms,
+                        "script": config.script.as_ref().map(|s| serde_json::json!({
+                            "max_operations": s.max_operations,
+                            "max_requests": s.max_requests,
+                        })),
+                        "tls": config.tls.as_ref().map(|t| serde_json::json!({
+                            "custom_ca": t.ca_cert_pem.is_some(),
+                            "client_certificate": t.client_cert_pem.is_some(),
+                            "insecure_skip_verify": t.insecure_skip_verify,
+                            "server_name": t.server_name,
+                        })),
+                        "hardware_info": {
+                            "cpu_cores": cpu_cores,
+                            "total_ram_mb": total_mem_kb as f64 / 1024.0,
+                            "free_ram_mb": free_mem_kb as f64 / 1024.0,
+                        }
+                    }
+                }).to_string());
+
+                if config.tls.as_ref().is_some_and(|t| t.insecure_skip_verify) {
+                    println!("WARNING: TLS certificate verification disabled for test {}", config.request_id);
+                    ctx.text(serde_json::json!({
+                        "status": "warning",
+                        "message": "TLS certificate verification is disabled (insecure_skip_verify)"
+                    }).to_string());
+                }
+
+                task::spawn(run_test(config, tls, jwt_signer, tx, TestControl {
+                    cancel_flag: Arc::clone(&self.cancel_flag),
+                    running_test: Arc::clone(&self.running_test),
+                }));
             }
             Ok(ws::Message::Close(_)) => {
                 ctx.stop();
@@ -378,20 +380,6 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
     }
 }
 
-fn calculate_median(data: &[f64]) -> f64 {
-    let mut sorted = data.to_owned();
-    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    let len = sorted.len();
-    if len == 0 {
-        return 0.0;
-    }
-    if len % 2 == 0 {
-        (sorted[len / 2 - 1] + sorted[len / 2]) / 2.0
-    } else {
-        sorted[len / 2]
-    }
-}
-
 pub async fn ws_handler(req: HttpRequest, stream: web::Payload) -> Result<HttpResponse, Error> {
     let expected_token = env::var("WS_SECRET_TOKEN").unwrap_or_default();
 
@@ -412,8 +400,7 @@ pub async fn ws_handler(req: HttpRequest, stream: web::Payload) -> Result<HttpRe
             if ACTIVE_CONNECTIONS.load(Ordering::SeqCst) >= MAX_CONNECTIONS {
                 return Ok(HttpResponse::TooManyRequests().body("There is already an active WebSocket connection"));
             }
-            let res = ws::start(WsSession::new(), &req, stream);
-            res
+            ws::start(WsSession::new(), &req, stream)
         }
         _ => Ok(HttpResponse::Unauthorized().finish()),
     }